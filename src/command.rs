@@ -1,31 +1,79 @@
 use std::collections::VecDeque;
 use std::mem::replace;
 use std::sync::Arc;
+use std::time::Duration;
 
 use base64::prelude::*;
 
+use poise::ChoiceParameter;
 use poise::CreateReply;
 use poise::command;
 
+use serenity::all::Attachment;
+use serenity::all::AutocompleteChoice;
+use serenity::all::ChannelId;
+use serenity::all::ComponentInteractionCollector;
 use serenity::all::GuildId;
+use serenity::all::Http;
 use serenity::async_trait;
+use serenity::builder::CreateActionRow;
+use serenity::builder::CreateButton;
 use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedFooter;
+use serenity::builder::CreateInteractionResponse;
+use serenity::builder::CreateInteractionResponseMessage;
 
-use serenity::builder::CreateEmbedAuthor;
 use songbird::{Event, TrackEvent, EventHandler, EventContext};
+use songbird::tracks::PlayMode;
 
 use tokio::sync::Mutex;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
 
-use tracing::error;
 use tracing::instrument;
+use tracing::warn;
+
+use url::Url;
 
 use crate::Context;
+use crate::config::CONFIG;
+use crate::sources::Source;
+use crate::sources::Track;
+use crate::sources::attachment;
+use crate::sources::attachment::AttachmentInfo;
+use crate::sources::audio_cache::AUDIO_CACHE;
+use crate::sources::catalog::MatchedTrack;
+use crate::sources::direct::DirectInfo;
+use crate::sources::fallback;
+use crate::sources::fallback::Hint;
+use crate::sources::fallback::Substitute;
+use crate::sources::local::LIBRARY;
+use crate::sources::local::Local;
+use crate::sources::meta_cache::METADATA;
+use crate::sources::playlist::PlaylistFormat;
+use crate::sources::podcast;
+use crate::sources::radio::RadioInfo;
+use crate::sources::split_query;
+use crate::sources::youtube;
+use crate::sources::youtube::ItemRange;
+use crate::sources::youtube::Youtube;
+use crate::sources::youtube::YoutubeLink;
+use crate::sources::youtube::PlaylistInfo;
+use crate::sources::youtube::ResultKind;
+use crate::sources::youtube::SearchOptions;
+use crate::sources::youtube::YoutubeInfo;
 use crate::sources::youtube::search_yt;
+use crate::sources::youtube::search_yt_playlist;
+use crate::sources::youtube::suggest_yt;
 use crate::structs::AudioLink;
 use crate::structs::Data;
+use crate::structs::DuplicatePolicy;
 use crate::structs::LoopPolicy;
+use crate::structs::ParseError;
 use crate::structs::ParseResult;
+use crate::structs::PlayerData;
 use crate::structs::PlayerState;
+use crate::structs::SearchItem;
 use crate::structs::UnloadedAudioLink;
 
 /// Show this help menu
@@ -99,7 +147,7 @@ enum JoinError {
 }
 
 async fn _join(ctx: Context<'_>) -> Result<Arc<Mutex<songbird::Call>>, JoinError> {
-    let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
+    let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
     let guild_id = ctx.guild_id().expect("Guild only command");
     let channel_id = ctx.guild().unwrap().voice_states
         .get(&ctx.author().id)
@@ -110,12 +158,15 @@ async fn _join(ctx: Context<'_>) -> Result<Arc<Mutex<songbird::Call>>, JoinError
                 {
                     let mut call = call.lock().await;
                     call.remove_all_global_events();
+                    // Tracks failing to play fire `End` too, with the error in their state
                     call.add_global_event(
                         Event::Track(TrackEvent::End),
                         TrackEndNotifier {
                             guild_id,
                             data: ctx.data().clone(),
                             songbird: manager,
+                            channel_id: ctx.channel_id(),
+                            http: ctx.serenity_context().http.clone(),
                         }
                     );
                 }
@@ -139,11 +190,17 @@ async fn _join(ctx: Context<'_>) -> Result<Arc<Mutex<songbird::Call>>, JoinError
 pub async fn leave(
     ctx: Context<'_>,
 ) -> anyhow::Result<()> {
-    let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
+    let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
     let guild_id = ctx.guild_id().expect("Guild Only Command");
     let mut state = ctx.data().get(guild_id);
     state.player.state = PlayerState::Offline;
+    state.player.track = None;
     state.player.queue.clear();
+    state.player.stops += 1;
+    // The announcements would keep going without anything playing
+    if let Some((_, announcer)) = state.player.announcer.take() {
+        announcer.abort();
+    }
     if let Some(call) = manager.get(guild_id) {
         (*call).lock().await.stop();
     }
@@ -167,30 +224,326 @@ pub async fn leave(
 #[instrument]
 pub async fn play(
     ctx: Context<'_>,
-    #[description = "The link of the music you want to play"]
-    #[description_localized("zh-TW", "想要播放音樂的連結")]
-    url: String,
+    #[description = "An audio file to play"]
+    #[description_localized("zh-TW", "想要播放的音訊檔案")]
+    attachment: Option<Attachment>,
+    #[description = "A link or keywords to search for, a range like `20-80` may follow a playlist link"]
+    #[description_localized("zh-TW", "想要播放音樂的連結，或是搜尋的關鍵字，播放清單連結後可加上範圍，例如 `20-80`")]
+    #[autocomplete = "autocomplete_play"]
+    #[rest]
+    url: Option<String>,
 ) -> anyhow::Result<()> {
     ctx.defer().await?;
     let guild_id = ctx.guild_id().expect("Guild Only Command");
-    let parse_result = AudioLink::parse(&url).await;
+    let attachments = match ctx {
+        poise::Context::Prefix(prefix) => prefix.msg.attachments.clone(),
+        _ => attachment.into_iter().collect(),
+    };
+    let url = url.unwrap_or_default();
+    if !attachments.is_empty() {
+        play_attachments(ctx, attachments).await?;
+        if url.trim().is_empty() {
+            return start_playing(ctx).await;
+        }
+    } else if url.trim().is_empty() {
+        ctx.say("Nothing to play, give me a link, some keywords or an audio file").await?;
+        return Ok(());
+    }
+    let mut links = url.split_whitespace().collect::<Vec<_>>();
+    // `play <playlist> 20-80`
+    let range = match links.as_slice() {
+        [link, range] if AudioLink::is_link(link) => ItemRange::parse(range),
+        _ => None,
+    };
+    if range.is_some() {
+        links.pop();
+    }
+    let youtube_link = match links.as_slice() {
+        [link] => Url::parse(link).ok().and_then(|url| YoutubeLink::parse(&url)),
+        _ => None,
+    };
+    let mut url = links.join(" ");
+    match youtube_link {
+        Some(YoutubeLink::Playlist(list)) => return play_playlist(ctx, list, range.unwrap_or(ItemRange::ALL)).await,
+        Some(YoutubeLink::VideoInList { list, .. }) if range.is_some() => {
+            return play_playlist(ctx, list, range.unwrap_or(ItemRange::ALL)).await;
+        },
+        Some(YoutubeLink::VideoInList { video, list }) => match ask_video_or_list(ctx).await? {
+            true => return play_playlist(ctx, list, ItemRange::ALL).await,
+            false => url = YoutubeLink::video_url(&video),
+        },
+        _ if range.is_some() => {
+            ctx.say("Item ranges only apply to Youtube playlists").await?;
+            return Ok(());
+        },
+        _ => {},
+    }
+    if links.len() > 1 && AudioLink::is_link(links[0]) {
+        return play_multiple(ctx, links).await;
+    }
+    let parse_result = if AudioLink::is_link(&url) {
+        AudioLink::parse(url.trim()).await
+    } else {
+        AudioLink::search(&url).await.map(ParseResult::Single)
+    };
     let mut state = ctx.data().get(guild_id);
     match parse_result {
         Ok(ParseResult::Single(audio)) => {
-            match state.player.state {
-                PlayerState::Playing(_) => { ctx.say("Added to queue!").await?; },
-                _ => { ctx.say(format!("Playing `{}`", audio)).await?; },
-            }
-            state.player.queue.push_back(audio);
+            let msg = enqueue_one(&mut state.player, audio);
+            ctx.say(msg).await?;
         },
         Ok(ParseResult::Multiple(audio_list, meta)) => {
-            ctx.say(format!("`{}`\n{} songs added to queue!", meta.title, audio_list.len())).await?;
-            state.player.queue.append(&mut audio_list.into());
+            let total = audio_list.len();
+            let policy = state.player.duplicate_policy;
+            let duplicates = state.player.enqueue(audio_list);
+            ctx.say(format!(
+                "`{}`\n{} songs added to queue!{}",
+                meta.title,
+                added_count(policy, total, duplicates),
+                duplicate_note(policy, duplicates),
+            )).await?;
         },
         Err(e) => {
-            ctx.say(format!("Error: {}\nOperation failed, no song added", e)).await?;
+            ctx.say(format!("Error: {}\nOperation failed, no song added", e.message(ctx.locale()))).await?;
         },
     };
+    drop(state);
+    start_playing(ctx).await
+}
+
+/// How often the reply is updated while loading a playlist
+const PLAYLIST_PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Enqueue the videos of a Youtube playlist as they are loaded, and show the progress in the reply
+async fn play_playlist(ctx: Context<'_>, list: String, range: ItemRange) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let reply = ctx.say("Loading the playlist...").await?;
+    let (tx, mut rx) = mpsc::channel(PLAYLIST_BUFFER);
+    let task = tokio::spawn(async move { youtube::stream_playlist(&list, range, tx).await });
+    let stops = ctx.data().get(guild_id).player.stops;
+
+    let mut title = None;
+    let mut added = 0;
+    let mut duplicates = 0;
+    let mut last_update = Instant::now();
+    while let Some(info) = rx.recv().await {
+        if title.is_none() {
+            title = info.playlist.clone();
+        }
+        {
+            let mut state = ctx.data().get(guild_id);
+            // Stopped, or left the channel after the first song started
+            let left = added > 0 && matches!(state.player.state, PlayerState::Offline);
+            if state.player.stops != stops || left {
+                drop(state);
+                task.abort();
+                let title = title.as_deref().unwrap_or("Unknown");
+                let msg = format!("`{title}`\nThe player stopped, loading stopped after {added} songs");
+                reply.edit(ctx, CreateReply::default().content(msg)).await?;
+                return Ok(());
+            }
+            duplicates += state.player.enqueue([AudioLink::new(info)]);
+        }
+        added += 1;
+        // Play the first song without waiting for the rest
+        if added == 1 {
+            if let Err(e) = start_playing(ctx).await {
+                task.abort();
+                let msg = format!("Error: {e}\nThe playlist stopped loading");
+                reply.edit(ctx, CreateReply::default().content(msg)).await?;
+                return Err(e);
+            }
+        }
+        if last_update.elapsed() >= PLAYLIST_PROGRESS_INTERVAL {
+            let title = title.as_deref().unwrap_or("Unknown");
+            reply.edit(ctx, CreateReply::default().content(format!("`{title}`\nLoading... {added} songs added so far"))).await?;
+            last_update = Instant::now();
+        }
+    }
+
+    let title = title.as_deref().unwrap_or("Unknown");
+    let policy = ctx.data().get(guild_id).player.duplicate_policy;
+    let note = duplicate_note(policy, duplicates);
+    let added = added_count(policy, added, duplicates);
+    let result = task.await?
+        .inspect_err(|e| warn!("Failed to load the playlist: {e}"))
+        .map_err(ParseError::from);
+    let msg = match result {
+        Ok(_) if added == 0 => "No video found in the playlist, no song added".to_string(),
+        Ok(_) => format!("`{title}`\n{added} songs added to queue!{note}"),
+        Err(e) if added == 0 => format!("Error: {}\nOperation failed, no song added", e.message(ctx.locale())),
+        Err(e) => format!("`{title}`\n{added} songs added to queue, the rest failed to load: {}{note}", e.message(ctx.locale())),
+    };
+    reply.edit(ctx, CreateReply::default().content(msg)).await?;
+    Ok(())
+}
+
+/// Videos buffered between the loading and the queue
+const PLAYLIST_BUFFER: usize = 16;
+
+/// Ask whether to play only the video or the whole playlist of a `watch?v=...&list=...` link,
+/// the video is played if nobody answers
+async fn ask_video_or_list(ctx: Context<'_>) -> anyhow::Result<bool> {
+    let ctx_id = ctx.id();
+    let video_button_id = format!("{ctx_id}video");
+    let list_button_id = format!("{ctx_id}list");
+    let buttons = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(&video_button_id).label("This video"),
+        CreateButton::new(&list_button_id).label("Whole playlist"),
+    ])];
+    let reply = ctx.send(
+        CreateReply::default()
+        .content("This video is in a playlist, what do you want to play?")
+        .components(buttons)
+    ).await?;
+
+    let author = ctx.author().id;
+    let press = ComponentInteractionCollector::new(ctx)
+        .filter({
+            let button_ids = [video_button_id.clone(), list_button_id.clone()];
+            move |press| button_ids.contains(&press.data.custom_id) && press.user.id == author
+        })
+        .timeout(Duration::from_secs(60))
+        .await;
+    let whole_list = press.as_ref().is_some_and(|press| press.data.custom_id == list_button_id);
+    let msg = match whole_list {
+        true => "Playing the whole playlist",
+        false => "Playing this video only",
+    };
+    match press {
+        Some(press) => {
+            press.create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().content(msg).components(vec![])
+                ),
+            ).await?;
+        },
+        None => {
+            reply.edit(ctx, CreateReply::default().content(msg).components(vec![])).await?;
+        },
+    }
+    Ok(whole_list)
+}
+
+/// Maximum amount of failed links listed in the reply of `play`
+const MAX_FAILURE_REPORT: usize = 10;
+
+/// Resolve several links concurrently, enqueue them in the given order
+async fn play_multiple(ctx: Context<'_>, links: Vec<&str>) -> anyhow::Result<()> {
+    let handles = links.iter()
+        .map(|link| tokio::spawn(AudioLink::parse(link.to_string())))
+        .collect::<Vec<_>>();
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await?);
+    }
+    let labels = links.iter().map(|link| format!("<{link}>")).collect();
+    enqueue_results(ctx, labels, results).await?;
+    start_playing(ctx).await
+}
+
+/// Validate and enqueue the uploaded audio files
+async fn play_attachments(ctx: Context<'_>, attachments: Vec<Attachment>) -> anyhow::Result<()> {
+    let labels = attachments.iter().map(|a| format!("`{}`", a.filename)).collect();
+    let handles = attachments.into_iter()
+        .map(|attachment| tokio::spawn(async move {
+            AttachmentInfo::from_attachment(&attachment).await
+                .inspect_err(|e| warn!("Failed to load the attachment `{}`: {e}", attachment.filename))
+                .map(|info| ParseResult::Single(AudioLink::new(info)))
+        }))
+        .collect::<Vec<_>>();
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await?);
+    }
+    enqueue_results(ctx, labels, results).await
+}
+
+/// Enqueue the successful results in order, and report the failed ones labeled by `labels`
+async fn enqueue_results(
+    ctx: Context<'_>,
+    labels: Vec<String>,
+    results: Vec<Result<ParseResult, ParseError>>,
+) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let total = results.len();
+    let mut added = 0;
+    let mut duplicates = 0;
+    let mut substituted = 0;
+    let mut failed = vec![];
+    let policy = {
+        let mut state = ctx.data().get(guild_id);
+        for (label, result) in labels.iter().zip(results) {
+            match result {
+                Ok(ParseResult::Single(audio)) => {
+                    if audio.downcast::<Substitute>().is_some() {
+                        substituted += 1;
+                    }
+                    duplicates += state.player.enqueue([audio]);
+                    added += 1;
+                },
+                Ok(ParseResult::Multiple(audio_list, _)) => {
+                    added += audio_list.len();
+                    duplicates += state.player.enqueue(audio_list);
+                },
+                Err(e) => failed.push(format!("- {label}: {}", e.message(ctx.locale()))),
+            }
+        }
+        state.player.duplicate_policy
+    };
+
+    let mut msg = format!("{} songs added to queue!", added_count(policy, added, duplicates));
+    msg += &duplicate_note(policy, duplicates);
+    if substituted > 0 {
+        msg += &format!("\n{substituted} of them failed to load and were replaced by other uploads, marked in `queue`");
+    }
+    if !failed.is_empty() {
+        msg += &format!("\nFailed to load {} of {}:", failed.len(), total);
+        for line in failed.iter().take(MAX_FAILURE_REPORT) {
+            msg += &format!("\n{line}");
+        }
+        if failed.len() > MAX_FAILURE_REPORT {
+            msg += &format!("\n...and {} more", failed.len() - MAX_FAILURE_REPORT);
+        }
+    }
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Add a single song to the queue, returns the reply telling what happened to it
+fn enqueue_one(player: &mut PlayerData, audio: AudioLink) -> String {
+    let title = audio.to_string();
+    let playing = matches!(player.state, PlayerState::Playing(_));
+    match (player.enqueue([audio]), player.duplicate_policy) {
+        (0, _) if playing => "Added to queue!".to_string(),
+        (0, _) => format!("Playing `{title}`"),
+        (_, DuplicatePolicy::Reject) => format!("`{title}` is already in the queue, not added"),
+        _ => format!("Added to queue! `{title}` was already in the queue"),
+    }
+}
+
+/// The amount of songs left in the queue out of `total`, the duplicates are skipped when rejected
+fn added_count(policy: DuplicatePolicy, total: usize, duplicates: usize) -> usize {
+    match policy {
+        DuplicatePolicy::Reject => total - duplicates,
+        _ => total,
+    }
+}
+
+/// Tell how the songs already in the queue were handled, empty when there were none
+fn duplicate_note(policy: DuplicatePolicy, duplicates: usize) -> String {
+    match (policy, duplicates) {
+        (_, 0) | (DuplicatePolicy::Allow, _) => String::new(),
+        (DuplicatePolicy::Warn, n) => format!("\n{n} of them were already in the queue"),
+        (DuplicatePolicy::Reject, n) => format!("\n{n} songs already in the queue were skipped"),
+    }
+}
+
+/// Join the voice channel if needed, and play the next song if the player is not playing
+async fn start_playing(ctx: Context<'_>) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let mut state = ctx.data().get(guild_id);
     if matches!(state.player.state, PlayerState::Offline) {
         match _join(ctx).await {
             Ok(_) => { state.player.state = PlayerState::Idle },
@@ -200,16 +553,88 @@ pub async fn play(
     }
     if !matches!(state.player.state, PlayerState::Playing(_)) {
         if let Some(audio) = state.player.queue.pop_front() {
-            state.player.state = PlayerState::Playing(audio.clone());
-            let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
+            let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
             let call = manager.get_or_insert(guild_id);
-            (*call).lock().await.play(audio.into());
+            state.player.play(&mut *call.lock().await, audio);
         }
     }
     Ok(())
 }
 
+/// Wait for the user to stop typing before searching
+const AUTOCOMPLETE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Time given to answer an autocomplete, Discord drops the answers after 3 seconds
+const AUTOCOMPLETE_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// Suggest Youtube videos for the `url` argument of `play`
+async fn autocomplete_play(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<AutocompleteChoice> {
+    if AudioLink::is_link(partial) {
+        return vec![];
+    }
+    let (source, query) = split_query(partial);
+    if source.key() != Youtube.key() || query.is_empty() {
+        return vec![];
+    }
+    let cache = &ctx.data().suggestion;
+    let list = match cache.get(query) {
+        Some(list) => list,
+        None => {
+            let user_id = ctx.author().id;
+            let ticket = cache.begin(user_id);
+            tokio::time::sleep(AUTOCOMPLETE_DEBOUNCE).await;
+            if !cache.is_latest(user_id, ticket) {
+                return vec![];
+            }
+            // A slow search keeps running and fills the cache for the next keystrokes
+            let search = tokio::spawn({
+                let cache = cache.clone();
+                let query = query.to_string();
+                async move {
+                    let list = suggest_yt(&query).await?;
+                    cache.insert(&query, list.clone());
+                    Ok::<_, youtube::Error>(list)
+                }
+            });
+            match tokio::time::timeout(AUTOCOMPLETE_TIMEOUT - AUTOCOMPLETE_DEBOUNCE, search).await {
+                Ok(Ok(Ok(list))) => list,
+                Ok(Ok(Err(e))) => {
+                    warn!("Autocomplete search failed: {e}");
+                    return vec![];
+                },
+                Ok(Err(e)) => {
+                    warn!("Autocomplete search task failed: {e}");
+                    return vec![];
+                },
+                Err(_) => return vec![],
+            }
+        },
+    };
+    list.into_iter()
+        .take(25)
+        .map(|info| {
+            let url = info.url();
+            let audio = AudioLink::from(info);
+            let mut name = format!("[{}] {}", audio.time_str(), audio);
+            if name.chars().count() > 100 {
+                name = name.chars().take(99).collect::<String>() + "…";
+            }
+            AutocompleteChoice::new(name, url)
+        })
+        .collect()
+}
+
 /// Search on Youtube
+///
+/// Filters can be added to the prompt:
+/// - `duration:short|medium|long`
+/// - `date:hour|today|week|month|year`
+/// - `sort:relevance|date|views|rating`
+/// - `type:video|playlist`
+/// - `channel:<name>` or `channel:"<name with spaces>"`
 #[command(
     prefix_command,
     slash_command,
@@ -219,35 +644,138 @@ pub async fn play(
 )]
 pub async fn search(
     ctx: Context<'_>,
-    #[description = "The search prompt"]
-    #[description_localized("zh-TW", "想要搜尋的關鍵字")]
+    #[description = "The search prompt, can include filters like duration:long sort:date type:playlist"]
+    #[description_localized("zh-TW", "想要搜尋的關鍵字，可加上篩選條件，例如 duration:long channel:\"Lofi Girl\"")]
     #[rest]
     prompt: String,
 ) -> anyhow::Result<()> {
     ctx.defer().await?;
+    let (options, kind, prompt) = SearchOptions::extract(&prompt);
+    let kind = kind.unwrap_or(ResultKind::Video);
+
+    let list = match kind {
+        ResultKind::Video => search_yt(&prompt, &options).await?
+            .into_iter()
+            .map(|info| SearchItem::Audio(info.into()))
+            .collect::<Vec<_>>(),
+        ResultKind::Playlist => search_yt_playlist(&prompt, &options).await?
+            .into_iter()
+            .map(SearchItem::Playlist)
+            .collect::<Vec<_>>(),
+    };
+    if list.is_empty() {
+        ctx.say("No result found").await?;
+        return Ok(());
+    }
+
+    let mut filters = options.to_string();
+    if kind == ResultKind::Playlist {
+        filters = match filters.is_empty() {
+            true => "Type: `playlist`".to_string(),
+            false => format!("Type: `playlist`, {filters}"),
+        };
+    }
+    show_selection(ctx, list, "Search Result", &filters).await
+}
+
+/// Store the options for `select`, and list them in pages
+async fn show_selection(ctx: Context<'_>, list: Vec<SearchItem>, title: &str, filters: &str) -> anyhow::Result<()> {
     let guild_id = ctx.guild_id().expect("Guild Only Command");
     let user_id = ctx.author().id;
-    let search_result = search_yt(&prompt).await?;
+    let page_count = list.len().div_ceil(SEARCH_PAGE_SIZE);
+    ctx.data().get(guild_id).player.search_item.insert(user_id, list.clone());
 
-    let emoji_str = ["one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "keycap_ten"];
-    let body = search_result.iter().zip(emoji_str)
-        .map(|(info, e)| format!(":{e}: `{}` [{}:{:02}]", info.title, info.duration / 60, info.duration % 60))
-        .fold("Use `]select <num>`, `/select <num>` or `]n <num>` to select:".to_string(), |acc, e| acc + "\n" + &e);
-    let list = search_result.into_iter().map(AudioLink::from).collect::<Vec<_>>();
-    let mut state = ctx.data().get(guild_id);
-    state.player.search_item.insert(user_id, list);
-    ctx.send(
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{ctx_id}prev");
+    let next_button_id = format!("{ctx_id}next");
+    let mut page = 0;
+    let reply = ctx.send(
         CreateReply::default()
-        .embed(
-            CreateEmbed::new()
-            .title("Search Result")
-            .description(body)
-        )
+        .embed(search_page(&list, page, title, filters))
+        .components(page_buttons(&prev_button_id, &next_button_id, page, page_count))
+    ).await?;
+
+    // Only the author pages through the results, the other users would move the page under them
+    let author = ctx.author().id;
+    let button_ids = [prev_button_id.clone(), next_button_id.clone()];
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .filter({
+            let button_ids = button_ids.clone();
+            move |press| button_ids.contains(&press.data.custom_id) && press.user.id == author
+        })
+        .timeout(Duration::from_secs(600))
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            page = (page + 1).min(page_count - 1);
+        } else {
+            page = page.saturating_sub(1);
+        }
+        press.create_response(
+            ctx.serenity_context(),
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                .embed(search_page(&list, page, title, filters))
+                .components(page_buttons(&prev_button_id, &next_button_id, page, page_count))
+            ),
+        ).await?;
+    }
+
+    // Navigation timed out, remove the buttons
+    reply.edit(
+        ctx,
+        CreateReply::default()
+        .embed(search_page(&list, page, title, filters))
+        .components(vec![])
     ).await?;
 
     Ok(())
 }
 
+const SEARCH_PAGE_SIZE: usize = 10;
+
+fn search_page(list: &[SearchItem], page: usize, title: &str, filters: &str) -> CreateEmbed {
+    let page_count = list.len().div_ceil(SEARCH_PAGE_SIZE);
+    let body = list.iter()
+        .enumerate()
+        .skip(page * SEARCH_PAGE_SIZE)
+        .take(SEARCH_PAGE_SIZE)
+        .map(|(i, item)| match item {
+            SearchItem::Audio(audio) => {
+                let mut line = format!("**{}.** `{}` [{}]", i + 1, audio, audio.time_str());
+                if let Some(DirectInfo { published: Some(published), .. }) = audio.downcast() {
+                    line += &format!(" {published}");
+                }
+                line
+            },
+            SearchItem::Playlist(info) => {
+                let mut line = format!("**{}.** :notepad_spiral: `{}`", i + 1, info.title);
+                if let Some(channel) = &info.channel {
+                    line += &format!(" by {channel}");
+                }
+                if let Some(count) = info.playlist_count {
+                    line += &format!(" [{count} videos]");
+                }
+                line
+            },
+        })
+        .fold("Use `]select <num>`, `/select <num>` or `]n <num>` to select:".to_string(), |acc, e| acc + "\n" + &e);
+    let mut embed = CreateEmbed::new()
+        .title(title)
+        .description(body);
+    if !filters.is_empty() {
+        embed = embed.field("Filters", filters, false);
+    }
+    embed.footer(CreateEmbedFooter::new(format!("Page {}/{} ({} results)", page + 1, page_count, list.len())))
+}
+
+fn page_buttons(prev_id: &str, next_id: &str, page: usize, page_count: usize) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(prev_id).emoji('◀').disabled(page == 0),
+        CreateButton::new(next_id).emoji('▶').disabled(page + 1 >= page_count),
+    ])]
+}
+
 /// Select from a set of options
 #[command(
     prefix_command,
@@ -270,7 +798,14 @@ pub async fn select(
 
     if let std::collections::hash_map::Entry::Occupied(entry) = entry {
         if index != 0 && index <= entry.get().len() {
-            let list = entry.remove();
+            let item = entry.remove().into_iter().nth(index - 1).expect("index in range");
+            let audio = match item {
+                SearchItem::Audio(audio) => audio,
+                SearchItem::Playlist(info) => {
+                    drop(state);
+                    return select_playlist(ctx, info).await;
+                },
+            };
             if matches!(state.player.state, PlayerState::Offline) {
                 match _join(ctx).await {
                     Ok(_) => { state.player.state = PlayerState::Idle },
@@ -278,16 +813,14 @@ pub async fn select(
                     Err(JoinError::NotInChannel) => { ctx.say("Not in a voice channel").await?; return Ok(()); },
                 }
             }
-            let audio = list.into_iter().nth(index - 1).expect("index in range").into();
             if matches!(state.player.state, PlayerState::Playing(_)) {
-                ctx.say("Added to queue!").await?;
-                state.player.queue.push_back(audio);
+                let msg = enqueue_one(&mut state.player, audio);
+                ctx.say(msg).await?;
             } else if matches!(state.player.state, PlayerState::Idle) {
                 ctx.say(format!("Playing `{}`", audio)).await?;
-                state.player.state = PlayerState::Playing(audio.clone());
-                let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
+                let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
                 let call = manager.get_or_insert(guild_id);
-                (*call).lock().await.play(audio.into());
+                state.player.play(&mut *call.lock().await, audio);
             }
         } else {
             ctx.say("Input not in range").await?;
@@ -299,6 +832,41 @@ pub async fn select(
     Ok(())
 }
 
+async fn select_playlist(ctx: Context<'_>, info: PlaylistInfo) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let youtube_link = Url::parse(&info.url).ok().and_then(|url| YoutubeLink::parse(&url));
+    if let Some(YoutubeLink::Playlist(list)) = youtube_link {
+        return play_playlist(ctx, list, ItemRange::ALL).await;
+    }
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let parse_result = AudioLink::parse(&info.url).await;
+    {
+        let mut state = ctx.data().get(guild_id);
+        match parse_result {
+            Ok(ParseResult::Single(audio)) => {
+                let msg = enqueue_one(&mut state.player, audio);
+                ctx.say(msg).await?;
+            },
+            Ok(ParseResult::Multiple(audio_list, meta)) => {
+                let total = audio_list.len();
+                let policy = state.player.duplicate_policy;
+                let duplicates = state.player.enqueue(audio_list);
+                ctx.say(format!(
+                    "`{}`\n{} songs added to queue!{}",
+                    meta.title,
+                    added_count(policy, total, duplicates),
+                    duplicate_note(policy, duplicates),
+                )).await?;
+            },
+            Err(e) => {
+                ctx.say(format!("Error: {}\nOperation failed, no song added", e.message(ctx.locale()))).await?;
+                return Ok(());
+            },
+        }
+    }
+    start_playing(ctx).await
+}
+
 /// Stop playing songs (clears the play queue)
 #[command(
     prefix_command,
@@ -316,8 +884,10 @@ pub async fn stop(
         _ => "Player stopped!",
     };
     state.player.state = PlayerState::Idle;
+    state.player.track = None;
     state.player.queue.clear();
-    let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
+    state.player.stops += 1;
+    let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
     let call = manager.get_or_insert(guild_id);
     (*call).lock().await.stop();
     ctx.say(msg).await?;
@@ -341,15 +911,15 @@ pub async fn skip(
         PlayerState::Offline => "The bot is not in a voice channel!",
         PlayerState::Idle => "The bot is not currently playing anything!",
         PlayerState::Playing(_) => {
-            let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
+            let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
             let call = manager.get_or_insert(guild_id);
             let mut call = (*call).lock().await;
             call.stop();
             if let Some(audio) = state.player.queue.pop_front() {
-                state.player.state = PlayerState::Playing(audio.clone());
-                call.play(audio.into());
+                state.player.play(&mut call, audio);
             } else {
                 state.player.state = PlayerState::Idle;
+                state.player.track = None;
             }
             "Skiped a song!"
         },
@@ -371,12 +941,14 @@ pub async fn queue(
 ) -> anyhow::Result<()> {
     let guild_id = ctx.guild_id().expect("Guild Only Command");
     let state = ctx.data().get(guild_id);
-    if state.player.queue.len() == 0 {
+    if state.player.queue.is_empty() {
         ctx.say("There's no song in the queue").await?;
         return Ok(());
     }
     let body = state.player.queue.iter()
-        .map(|entry| format!("- `{}` [{}]", entry, entry.time_str()))
+        .enumerate()
+        .map(|(i, entry)| format!("**{}.** `{}` [{}]{}", i + 1, entry, entry.time_str(),
+            entry.note().map(|note| format!(" {note}")).unwrap_or_default()))
         .fold(format!("Total of {} songs:", state.player.queue.len()), |acc, e| acc + "\n" + &e);
     ctx.send(
         CreateReply::default()
@@ -401,24 +973,23 @@ pub async fn now_playing(
     ctx: Context<'_>,
 ) -> anyhow::Result<()> {
     let guild_id = ctx.guild_id().expect("Guild Only Command");
-    let state = ctx.data().get(guild_id);
-    if let PlayerState::Playing(ref audio) = state.player.state {
-        let embed = match audio {
-            AudioLink::Youtube(info) => {
-                let mut m = CreateEmbed::new()
-                    .title(&info.title)
-                    .url(format!("https://www.youtube.com/watch?v={}", info.id))
-                    .field("Channel", &info.channel, true)
-                    .field("Duration", audio.time_str(), true)
-                    .author(CreateEmbedAuthor::new("Audio source from Youtube"));
-                if let Some(desc) = &info.description {
-                    m = m.description(desc);
-                }
-                if let Some(playlist) = &info.playlist {
-                    m = m.field("Playlist", playlist, true);
-                }
-                m.field("Channel URL", &info.channel_url, false)
-            },
+    let (playing, track, from_cache) = {
+        let state = ctx.data().get(guild_id);
+        let playing = match &state.player.state {
+            PlayerState::Playing(audio) => Some(audio.clone()),
+            _ => None,
+        };
+        (playing, state.player.track.clone(), state.player.from_cache)
+    };
+    if let Some(ref audio) = playing {
+        let position = match &track {
+            Some(track) => track.get_info().await.map(|info| info.position.as_secs() as u32).ok(),
+            None => None,
+        };
+        let embed = audio.embed(position).await;
+        let embed = match from_cache {
+            true => embed.footer(CreateEmbedFooter::new("Played from the audio cache")),
+            false => embed,
         };
         ctx.send(CreateReply::default().embed(embed)).await?;
     } else {
@@ -427,6 +998,216 @@ pub async fn now_playing(
     Ok(())
 }
 
+/// Show the Youtube videos matched to a music service track, or pick another one
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    description_localized("zh-TW", "顯示或修正音樂服務曲目所對應的 Youtube 影片"),
+)]
+pub async fn rematch(
+    ctx: Context<'_>,
+    #[description = "Position in the queue, 0 for the song on play"]
+    #[description_localized("zh-TW", "在歌單中的位置，0 為正在播放的歌曲")]
+    position: usize,
+    #[description = "A candidate number, a Youtube link or keywords to search for"]
+    #[description_localized("zh-TW", "候選編號、Youtube 連結或搜尋關鍵字")]
+    #[rest]
+    choice: Option<String>,
+) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let matched = {
+        let state = ctx.data().get(guild_id);
+        let audio = match position {
+            0 => match &state.player.state {
+                PlayerState::Playing(audio) => Some(audio),
+                _ => None,
+            },
+            n => state.player.queue.get(n - 1),
+        };
+        match audio.and_then(|audio| audio.downcast::<MatchedTrack>()) {
+            Some(matched) => matched.clone(),
+            _ => {
+                drop(state);
+                ctx.say("There's no music service track at this position").await?;
+                return Ok(());
+            },
+        }
+    };
+    let choice = choice.unwrap_or_default();
+    let choice = choice.trim();
+    if choice.is_empty() {
+        ctx.send(CreateReply::default().embed(match_list(&matched, position))).await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    let video = if let Ok(n) = choice.parse::<usize>() {
+        match n.checked_sub(1).and_then(|i| matched.candidates.get(i)) {
+            Some((video, _)) => video.clone(),
+            None => {
+                ctx.say("Input not in range").await?;
+                return Ok(());
+            },
+        }
+    } else if AudioLink::is_link(choice) {
+        match AudioLink::parse(choice).await {
+            Ok(ParseResult::Single(audio)) if audio.downcast::<YoutubeInfo>().is_some() => {
+                audio.downcast::<YoutubeInfo>().cloned().expect("Checked by the guard")
+            },
+            Ok(_) => {
+                ctx.say("Give me the link of a single Youtube video").await?;
+                return Ok(());
+            },
+            Err(e) => {
+                ctx.say(format!("Error: {}", e.message(ctx.locale()))).await?;
+                return Ok(());
+            },
+        }
+    } else {
+        match search_yt(choice, &SearchOptions::default()).await.map(|list| list.into_iter().next()) {
+            Ok(Some(info)) => info,
+            Ok(None) => {
+                ctx.say(format!("No result found for `{choice}`")).await?;
+                return Ok(());
+            },
+            Err(e) => {
+                ctx.say(format!("Search failed: {e}")).await?;
+                return Ok(());
+            },
+        }
+    };
+
+    let msg = format!("`{}` is now played from `{}`", matched.title(), video.title);
+    let mut state = ctx.data().get(guild_id);
+    // The queue may have changed while searching
+    let target = match position {
+        0 => match &mut state.player.state {
+            PlayerState::Playing(audio) => Some(audio),
+            _ => None,
+        },
+        n => state.player.queue.get_mut(n - 1),
+    };
+    let audio = match target {
+        Some(target) if target.downcast::<MatchedTrack>().is_some_and(|target| target.track.link == matched.track.link) => {
+            let mut picked = matched;
+            picked.pick(video);
+            *target = AudioLink::new(picked);
+            target.clone()
+        },
+        _ => {
+            drop(state);
+            ctx.say("The queue has changed, please try again").await?;
+            return Ok(());
+        },
+    };
+    if position == 0 {
+        // Restart the song with the new video
+        if let Some(track) = state.player.track.take() {
+            let _ = track.stop();
+        }
+        let manager = songbird::get(ctx.serenity_context()).await.expect("Songbird Not initialized");
+        let call = manager.get_or_insert(guild_id);
+        state.player.play(&mut *call.lock().await, audio);
+    }
+    drop(state);
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// List the scored candidates of a music service track
+fn match_list(matched: &MatchedTrack, position: usize) -> CreateEmbed {
+    let body = matched.candidates.iter()
+        .enumerate()
+        .map(|(i, (video, score))| {
+            let line = format!(
+                "**{}.** `{}` by {} [{}:{:02}] {:.0}%",
+                i + 1, video.title, video.channel, video.duration / 60, video.duration % 60, score * 100.0,
+            );
+            match video.id == matched.video.id {
+                true => format!(":arrow_forward: {line}"),
+                false => line,
+            }
+        })
+        .fold(
+            format!("Use `]rematch {position} <num>` to pick another one, or give a Youtube link or keywords:"),
+            |acc, e| acc + "\n" + &e,
+        );
+    CreateEmbed::new()
+        .title(format!("Matches for `{} - {}`", matched.track.artist, matched.track.title))
+        .url(&matched.track.link)
+        .description(body)
+        .field(
+            "Played from",
+            format!("[{}]({}) ({:.0}% match)", matched.video.title, matched.video.url(), matched.confidence * 100.0),
+            false,
+        )
+}
+
+/// How often the song on air is checked for `announce`
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Toggle announcing the songs on air of radio streams in this channel
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    description_localized("zh-TW", "切換是否在此頻道通知電台正在播放的歌曲"),
+)]
+pub async fn announce(
+    ctx: Context<'_>,
+) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let channel_id = ctx.channel_id();
+    let mut state = ctx.data().get(guild_id);
+    let msg = match state.player.announcer.take() {
+        Some((channel, handle)) if channel == channel_id => {
+            handle.abort();
+            "Radio announcements turned off"
+        },
+        previous => {
+            if let Some((_, handle)) = previous {
+                handle.abort();
+            }
+            let task = tokio::spawn(announce_on_air(
+                ctx.data().clone(),
+                guild_id,
+                ctx.serenity_context().http.clone(),
+                channel_id,
+            ));
+            state.player.announcer = Some((channel_id, task.abort_handle()));
+            "The songs on air of radio streams will be announced in this channel"
+        },
+    };
+    drop(state);
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Post the song on air whenever it changes, runs until aborted by `announce`
+async fn announce_on_air(data: Data, guild_id: GuildId, http: Arc<Http>, channel_id: ChannelId) {
+    let mut last = None;
+    loop {
+        tokio::time::sleep(ANNOUNCE_INTERVAL).await;
+        let on_air = match &data.get(guild_id).player.state {
+            PlayerState::Playing(audio) => audio.downcast::<RadioInfo>()
+                .and_then(|info| Some((info.name.clone(), info.on_air()?))),
+            _ => None,
+        };
+        let Some((station, title)) = on_air else {
+            last = None;
+            continue;
+        };
+        if last.as_ref() == Some(&title) {
+            continue;
+        }
+        if let Err(e) = channel_id.say(&http, format!(":radio: Now on air at **{station}**: `{title}`")).await {
+            warn!("Failed to announce the song on air: {e}");
+        }
+        last = Some(title);
+    }
+}
+
 /// Set the loop mode of the queue
 #[command(
     prefix_command,
@@ -447,54 +1228,161 @@ pub async fn cmd_loop(
     let msg = match state.player.loop_policy {
         LoopPolicy::Normal  => "Mode changed to `Normal`!",
         LoopPolicy::Loop    => "Mode changed to `Loop`!",
-        LoopPolicy::Random  => "Mode changed to `Random`!",
     };
     ctx.say(msg).await?;
     Ok(())
 }
 
-/// Import the play queue
+/// Set what happens to the songs added while they are already in the queue
 #[command(
     prefix_command,
     slash_command,
     guild_only,
-    description_localized("zh-TW", "匯入播放佇列"),
+    description_localized("zh-TW", "設定重複加入佇列的歌曲如何處理"),
+)]
+pub async fn duplicates(
+    ctx: Context<'_>,
+    #[description = "Allow, warn about or reject the songs already in the queue, omit to show the current policy"]
+    #[description_localized("zh-TW", "允許、警告或拒絕已在佇列中的歌曲，省略則顯示目前的設定")]
+    policy: Option<DuplicatePolicy>,
+) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let msg = {
+        let mut state = ctx.data().get(guild_id);
+        match policy {
+            Some(policy) => {
+                state.player.duplicate_policy = policy;
+                format!("Duplicate policy changed to `{}`!", policy.name())
+            },
+            None => format!("The duplicate policy is `{}`", state.player.duplicate_policy.name()),
+        }
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Largest playlist file accepted by `import`, in bytes
+const MAX_PLAYLIST_FILE_SIZE: u32 = 1024 * 1024;
+
+/// A pending link resolution, labeled for the failure report
+type PendingEntry = (String, tokio::task::JoinHandle<Result<ParseResult, ParseError>>);
+
+/// Import the play queue, or a playlist file (M3U, PLS or XSPF)
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    description_localized("zh-TW", "匯入播放佇列或播放清單檔案（M3U、PLS 或 XSPF）"),
     required_bot_permissions = "CONNECT | SPEAK",
 )]
 pub async fn import(
     ctx: Context<'_>,
-    #[description = "Input data"]
-    #[description_localized("zh-TW", "輸入資料")]
-    input: String,
+    #[description = "A playlist file (M3U, PLS or XSPF)"]
+    #[description_localized("zh-TW", "播放清單檔案（M3U、PLS 或 XSPF）")]
+    file: Option<Attachment>,
+    #[description = "An exported queue, or the content of a playlist file"]
+    #[description_localized("zh-TW", "匯出的播放佇列，或播放清單檔案的內容")]
+    #[rest]
+    input: Option<String>,
 ) -> anyhow::Result<()> {
-    let guild_id = ctx.guild_id().expect("Guild Only Command");
-    let mut state = ctx.data().get(guild_id);
-    let bin = BASE64_STANDARD.decode(input)?;
-    let queue: Vec<UnloadedAudioLink> = serde_cbor::from_slice(&bin)?;
-    ctx.say(format!("Adding {} songs! (Please wait while loading)", queue.len())).await?;
-    let handles = queue.into_iter()
-        .map(UnloadedAudioLink::load)
-        .map(tokio::task::spawn);
-    for handle in handles {
-        state.player.queue.push_back(handle.await??);
-    }
-    ctx.say("Done loading").await?;
-    if matches!(state.player.state, PlayerState::Offline) {
-        match _join(ctx).await {
-            Ok(_) => { state.player.state = PlayerState::Idle },
-            Err(JoinError::Failed(e)) => { ctx.say(format!("Join failed: {e:?}")).await?; return Ok(()); },
-            Err(JoinError::NotInChannel) => { ctx.say("Not in a voice channel").await?; return Ok(()); },
+    ctx.defer().await?;
+    let file = match ctx {
+        poise::Context::Prefix(prefix) => prefix.msg.attachments.first().cloned(),
+        _ => file,
+    };
+    let input = input.unwrap_or_default();
+    let pending = if let Some(file) = file {
+        if file.size > MAX_PLAYLIST_FILE_SIZE {
+            ctx.say(format!("The file is too large, the limit is {} KiB", MAX_PLAYLIST_FILE_SIZE / 1024)).await?;
+            return Ok(());
         }
-    }
-    if !matches!(state.player.state, PlayerState::Playing(_)) {
-        if let Some(audio) = state.player.queue.pop_front() {
-            state.player.state = PlayerState::Playing(audio.clone());
-            let manager = songbird::get(&ctx.serenity_context()).await.expect("Songbird Not initialized");
-            let call = manager.get_or_insert(guild_id);
-            (*call).lock().await.play(audio.into());
+        let text = String::from_utf8_lossy(&file.download().await?).into_owned();
+        let format = PlaylistFormat::from_path(&file.filename)
+            .or_else(|| PlaylistFormat::from_content_type(file.content_type.as_deref()?))
+            .or_else(|| PlaylistFormat::detect(&text));
+        let Some(format) = format else {
+            ctx.say(format!("`{}` is not a supported playlist file, use M3U, PLS or XSPF", file.filename)).await?;
+            return Ok(());
+        };
+        import_playlist(format, &text)
+    } else if let Some(format) = PlaylistFormat::detect(&input) {
+        import_playlist(format, &input)
+    } else if input.trim().is_empty() {
+        ctx.say("Nothing to import, give me an exported queue or a playlist file").await?;
+        return Ok(());
+    } else {
+        match import_queue(input.trim()) {
+            Ok(pending) => pending,
+            Err(e) => {
+                ctx.say(format!("The input is neither an exported queue nor a playlist: {e}")).await?;
+                return Ok(());
+            },
         }
+    };
+    if pending.is_empty() {
+        ctx.say("No entry found in the playlist").await?;
+        return Ok(());
     }
-    Ok(())
+
+    ctx.say(format!("Adding {} songs! (Please wait while loading)", pending.len())).await?;
+    let mut labels = Vec::with_capacity(pending.len());
+    let mut results = Vec::with_capacity(pending.len());
+    for (label, handle) in pending {
+        labels.push(label);
+        results.push(handle.await?);
+    }
+    enqueue_results(ctx, labels, results).await?;
+    start_playing(ctx).await
+}
+
+/// Load the links of a queue exported by `export`
+fn import_queue(input: &str) -> anyhow::Result<Vec<PendingEntry>> {
+    let bin = BASE64_STANDARD.decode(input)?;
+    let queue: Vec<UnloadedAudioLink> = serde_cbor::from_slice(&bin)?;
+    Ok(queue.into_iter()
+        .map(|link| {
+            let label = format!("`{}`", link.id);
+            let handle = tokio::spawn(async move {
+                let hint = fallback::is_enabled().then(|| Hint::from_unloaded(&link)).flatten();
+                let id = link.id.clone();
+                let result = link.load().await
+                    .map(ParseResult::Single)
+                    .inspect_err(|e| warn!("Failed to load `{id}`: {e:#}"))
+                    .map_err(ParseError::from);
+                fallback::or_substitute(result, hint).await
+            });
+            (label, handle)
+        })
+        .collect())
+}
+
+/// Resolve the entries of a playlist file like the links given to `play`
+fn import_playlist(format: PlaylistFormat, text: &str) -> Vec<PendingEntry> {
+    format.parse(text)
+        .into_iter()
+        .map(|entry| {
+            let label = match &entry.title {
+                Some(title) => format!("`{title}`"),
+                None => format!("<{}>", entry.location),
+            };
+            let handle = tokio::spawn(async move {
+                // Entries with a title can be searched for if they fail
+                let hint = entry.title.clone().map(|title| Hint {
+                    title,
+                    channel: None,
+                    duration: entry.duration.unwrap_or_default(),
+                    link: Some(entry.location.clone()),
+                    video_id: None,
+                });
+                let result = match AudioLink::is_link(&entry.location) {
+                    true => AudioLink::parse(entry.location).await,
+                    false => Err(ParseError::InvalidUrl(entry.location)),
+                };
+                fallback::or_substitute(result, hint).await
+            });
+            (label, handle)
+        })
+        .collect()
 }
 
 /// Export the play queue
@@ -513,38 +1401,441 @@ pub async fn export(
     if let PlayerState::Playing(ref audio) = state.player.state {
         value.push_front(audio.unload());
     }
+    let attachment_count = value.iter()
+        .filter(|link| link.source == attachment::Discord.key())
+        .count();
     let output = serde_cbor::to_vec(&value)?;
     let output = BASE64_STANDARD.encode(output);
-    ctx.say(format!("The exported queue:\n`{output}`")).await?;
+    let mut msg = format!("The exported queue:\n`{output}`");
+    if attachment_count > 0 {
+        msg += &format!("\n:warning: {attachment_count} songs are Discord attachments, their links expire after a while and may fail to import later");
+    }
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// List the episodes of a podcast to pick with `select`
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    aliases("pod"),
+    description_localized("zh-TW", "列出 Podcast 的單集以供選擇"),
+)]
+pub async fn podcast(
+    ctx: Context<'_>,
+    #[description = "The link of the RSS or Atom feed"]
+    #[description_localized("zh-TW", "RSS 或 Atom 訂閱的連結")]
+    feed: String,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    match podcast::fetch_feed(feed.trim()).await {
+        Ok(feed) => {
+            let list = feed.episodes.into_iter()
+                .map(|episode| SearchItem::Audio(AudioLink::new(episode)))
+                .collect();
+            show_selection(ctx, list, &feed.title, "").await
+        },
+        Err(e) => {
+            ctx.say(format!("Error: {e}")).await?;
+            Ok(())
+        },
+    }
+}
+
+/// Play music from the local library
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    aliases("lib"),
+    subcommands("library_search", "library_play", "library_rescan"),
+    subcommand_required,
+    description_localized("zh-TW", "播放本地音樂庫的音樂"),
+)]
+pub async fn library(
+    _ctx: Context<'_>,
+) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Search in the local library
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "search",
+    aliases("se"),
+    description_localized("zh-TW", "搜尋本地音樂庫"),
+)]
+pub async fn library_search(
+    ctx: Context<'_>,
+    #[description = "Words in the title, artist, album or file name"]
+    #[description_localized("zh-TW", "標題、演出者、專輯或檔名中的關鍵字")]
+    #[rest]
+    prompt: String,
+) -> anyhow::Result<()> {
+    let list = match Local.search(&prompt).await {
+        Ok(list) => list,
+        Err(e) => {
+            ctx.say(format!("Error: {e}")).await?;
+            return Ok(());
+        },
+    };
+    if list.is_empty() {
+        let msg = match LIBRARY.len() {
+            0 => "The library is empty, try `library rescan`",
+            _ => "No result found",
+        };
+        ctx.say(msg).await?;
+        return Ok(());
+    }
+    let list = list.into_iter().map(SearchItem::Audio).collect();
+    show_selection(ctx, list, "Library Search Result", "").await
+}
+
+/// Play the best match in the local library
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "play",
+    aliases("p"),
+    description_localized("zh-TW", "播放本地音樂庫中最符合的歌曲"),
+    required_bot_permissions = "CONNECT | SPEAK",
+)]
+pub async fn library_play(
+    ctx: Context<'_>,
+    #[description = "Words in the title, artist, album or file name"]
+    #[description_localized("zh-TW", "標題、演出者、專輯或檔名中的關鍵字")]
+    #[rest]
+    prompt: String,
+) -> anyhow::Result<()> {
+    let guild_id = ctx.guild_id().expect("Guild Only Command");
+    let audio = match Local.search(&prompt).await {
+        Ok(list) => list.into_iter().next(),
+        Err(e) => {
+            ctx.say(format!("Error: {e}")).await?;
+            return Ok(());
+        },
+    };
+    let Some(audio) = audio else {
+        ctx.say("No result found").await?;
+        return Ok(());
+    };
+    {
+        let mut state = ctx.data().get(guild_id);
+        let msg = enqueue_one(&mut state.player, audio);
+        ctx.say(msg).await?;
+    }
+    start_playing(ctx).await
+}
+
+/// Scan the local library again
+#[command(
+    prefix_command,
+    slash_command,
+    guild_only,
+    rename = "rescan",
+    description_localized("zh-TW", "重新掃描本地音樂庫"),
+)]
+pub async fn library_rescan(
+    ctx: Context<'_>,
+) -> anyhow::Result<()> {
+    ctx.defer().await?;
+    let msg = match LIBRARY.rescan().await {
+        Ok(count) => format!("Scan finished, {count} songs in the library"),
+        Err(e) => format!("Error: {e}"),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Inspect or flush the cached metadata and audio, only for the bot owners
+#[command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    subcommands("cache_info", "cache_flush", "cache_purge_audio"),
+    subcommand_required,
+    description_localized("zh-TW", "檢視或清除快取的資料（限機器人擁有者）"),
+)]
+pub async fn cache(
+    _ctx: Context<'_>,
+) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Amount of keys listed by `cache info`
+const CACHE_KEY_LIST_SIZE: usize = 15;
+
+/// Show the usage of the metadata cache, and the keys containing the filter
+#[command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    rename = "info",
+    description_localized("zh-TW", "顯示快取的使用狀況，以及包含關鍵字的項目"),
+)]
+pub async fn cache_info(
+    ctx: Context<'_>,
+    #[description = "Only list the keys containing this, e.g. `yt:search`"]
+    #[description_localized("zh-TW", "只列出包含此字串的項目，例如 `yt:search`")]
+    #[rest]
+    filter: Option<String>,
+) -> anyhow::Result<()> {
+    if !METADATA.is_enabled() && !AUDIO_CACHE.is_enabled() {
+        ctx.say("The caches are disabled").await?;
+        return Ok(());
+    }
+    let mut reply = CreateReply::default();
+    if METADATA.is_enabled() {
+        reply = reply.embed(metadata_cache_info(filter.as_deref()));
+    }
+    if AUDIO_CACHE.is_enabled() {
+        let stats = AUDIO_CACHE.stats();
+        reply = reply.embed(CreateEmbed::new()
+            .title("Audio Cache")
+            .field("Files", stats.files.to_string(), true)
+            .field("Size", format!(
+                "{:.1} / {} MiB",
+                stats.size as f64 / 1024.0 / 1024.0,
+                CONFIG.cache.audio_max_size / 1024 / 1024,
+            ), true));
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// The stats of the metadata cache, and the keys containing the filter
+fn metadata_cache_info(filter: Option<&str>) -> CreateEmbed {
+    let stats = METADATA.stats();
+    let mut embed = CreateEmbed::new()
+        .title("Metadata Cache")
+        .field("Entries", stats.entries.to_string(), true)
+        .field("Size", format!(
+            "{:.1} / {} MiB",
+            stats.size as f64 / 1024.0 / 1024.0,
+            CONFIG.cache.metadata_max_size / 1024 / 1024,
+        ), true)
+        .field("Hits / Misses", format!("{} / {}", stats.hits, stats.misses), true)
+        .field("TTL", age_str(CONFIG.cache.metadata_ttl), true);
+    if let Some(oldest) = stats.oldest {
+        embed = embed.field("Oldest entry", age_str(oldest), true);
+    }
+    if let Some(filter) = filter.map(str::trim).filter(|f| !f.is_empty()) {
+        let keys = METADATA.keys(filter);
+        let mut list = keys.iter()
+            .take(CACHE_KEY_LIST_SIZE)
+            .map(|(key, age)| format!("- `{key}` ({} ago)", age_str(*age)))
+            .collect::<Vec<_>>();
+        if keys.len() > CACHE_KEY_LIST_SIZE {
+            list.push(format!("...and {} more", keys.len() - CACHE_KEY_LIST_SIZE));
+        }
+        let body = match list.is_empty() {
+            true => "No matching entry".to_string(),
+            false => list.join("\n"),
+        };
+        embed = embed.description(format!("Keys containing `{filter}`:\n{body}"));
+    }
+    embed
+}
+
+/// Remove the cached metadata, or only the keys containing the filter
+#[command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    rename = "flush",
+    description_localized("zh-TW", "清除快取的資料，或只清除包含關鍵字的項目"),
+)]
+pub async fn cache_flush(
+    ctx: Context<'_>,
+    #[description = "Only remove the keys containing this, e.g. `yt:search`"]
+    #[description_localized("zh-TW", "只清除包含此字串的項目，例如 `yt:search`")]
+    #[rest]
+    filter: Option<String>,
+) -> anyhow::Result<()> {
+    let count = METADATA.flush(filter.as_deref().map(str::trim).unwrap_or_default());
+    let msg = match METADATA.save().await {
+        Ok(_) => format!("Removed {count} entries"),
+        Err(e) => format!("Removed {count} entries, but the cache file was not updated: {e}"),
+    };
+    ctx.say(msg).await?;
     Ok(())
 }
 
+/// Remove the downloaded audio files
+#[command(
+    prefix_command,
+    slash_command,
+    owners_only,
+    rename = "purge-audio",
+    description_localized("zh-TW", "刪除快取的音訊檔案"),
+)]
+pub async fn cache_purge_audio(
+    ctx: Context<'_>,
+) -> anyhow::Result<()> {
+    if !AUDIO_CACHE.is_enabled() {
+        ctx.say("The audio cache is disabled").await?;
+        return Ok(());
+    }
+    let count = AUDIO_CACHE.purge();
+    ctx.say(format!("Removed {count} audio files")).await?;
+    Ok(())
+}
+
+/// Format a duration like `3d 4h`, `2h 5m` or `12m`
+fn age_str(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+/// Songs ending earlier than this before their duration are resumed
+const RESUME_MARGIN: Duration = Duration::from_secs(10);
+
+/// Live streams playing for this long before dropping get their reconnection count reset
+const LIVE_STABLE: Duration = Duration::from_secs(10 * 60);
+
 struct TrackEndNotifier {
     guild_id: GuildId,
     data: Data,
     songbird: Arc<songbird::Songbird>,
+    /// Where the failures are reported, the channel of the command that joined
+    channel_id: ChannelId,
+    http: Arc<Http>,
 }
 
 #[async_trait]
 impl EventHandler for TrackEndNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track(_) = ctx {
+        if let EventContext::Track(tracks) = ctx {
             let mut state = self.data.get(self.guild_id);
-            let next_state = if let Some(next_song) = state.player.queue.pop_front() {
+            // Tracks replaced by `skip` or `rematch` end as well, only the one on play advances the queue
+            let current = tracks.iter()
+                .find(|(_, handle)| state.player.track.as_ref().is_some_and(|track| track.uuid() == handle.uuid()));
+            let (track_state, _) = current?;
+            let error = match &track_state.playing {
+                PlayMode::Errored(e) => Some(e.to_string()),
+                _ => None,
+            };
+            // The stream dropped if the song ended well before its duration,
+            // live streams never end unless their connection drops, `skip` and `stop` replace the track first
+            let position = track_state.position;
+            let dropped = match &state.player.state {
+                PlayerState::Playing(audio) if audio.is_live() => !position.is_zero(),
+                PlayerState::Playing(audio) => {
+                    !position.is_zero() && position + RESUME_MARGIN < Duration::from_secs(audio.duration().into())
+                },
+                _ => false,
+            };
+            let live = matches!(&state.player.state, PlayerState::Playing(audio) if audio.is_live());
+            if dropped && live && position >= LIVE_STABLE {
+                state.player.resumes = 0;
+            }
+            if dropped && state.player.resumes < CONFIG.player.max_resumes {
                 let call = self.songbird.get_or_insert(self.guild_id);
-                (*call).lock().await.play(next_song.clone().into());
-                PlayerState::Playing(next_song)
-            } else {
-                PlayerState::Idle
+                state.player.resume(&mut *call.lock().await, position);
+                let PlayerState::Playing(audio) = &state.player.state else { return None };
+                warn!("The stream of `{audio}` dropped at {position:?}, resuming");
+                let msg = format!(
+                    ":arrows_counterclockwise: The stream of `{audio}` dropped at {}:{:02}, resuming ({}/{})",
+                    position.as_secs() / 60, position.as_secs() % 60, state.player.resumes, CONFIG.player.max_resumes,
+                );
+                drop(state);
+                if let Err(e) = self.channel_id.say(&self.http, msg).await {
+                    warn!("Failed to report the resume: {e}");
+                }
+                return None;
+            }
+            let prev_state = replace(&mut state.player.state, PlayerState::Idle);
+            state.player.track = None;
+            // Look for another upload of the failed song before moving on
+            let hint = match (&error, &prev_state) {
+                (Some(_), PlayerState::Playing(audio)) if fallback::is_enabled() => audio.hint(),
+                _ => None,
+            };
+            let substitute = match hint {
+                Some(hint) => {
+                    drop(state);
+                    let substitute = fallback::find_substitute(&hint).await;
+                    state = self.data.get(self.guild_id);
+                    substitute
+                },
+                None => None,
             };
-            let prev_state = replace(&mut state.player.state, next_state);
-            if let PlayerState::Playing(audio) = prev_state {
-                match state.player.loop_policy {
-                    LoopPolicy::Normal => {},
-                    LoopPolicy::Loop => {
-                        state.player.queue.push_back(audio.clone());
+            let gave_up = match (&error, &substitute) {
+                (Some(_), None) => {
+                    state.player.failures += 1;
+                    state.player.failures >= CONFIG.player.max_failures
+                },
+                (Some(_), Some(_)) => false,
+                (None, _) => {
+                    state.player.failures = 0;
+                    false
+                },
+            };
+            let next_song = match &substitute {
+                Some(substitute) => Some(AudioLink::new(substitute.clone())),
+                None if gave_up => None,
+                None => state.player.queue.pop_front(),
+            };
+            if gave_up {
+                state.player.failures = 0;
+            }
+            if let Some(next_song) = next_song {
+                // Another song may have been started while searching
+                match state.player.state {
+                    PlayerState::Playing(_) => state.player.queue.push_front(next_song),
+                    _ => {
+                        let call = self.songbird.get_or_insert(self.guild_id);
+                        state.player.play(&mut *call.lock().await, next_song);
                     },
-                    LoopPolicy::Random => {},
+                }
+            }
+            let PlayerState::Playing(audio) = prev_state else {
+                return None;
+            };
+            match state.player.loop_policy {
+                // Broken songs are not looped
+                LoopPolicy::Loop if error.is_none() => {
+                    state.player.queue.push_back(audio.clone());
+                },
+                _ => {},
+            }
+            drop(state);
+
+            if dropped && error.is_none() {
+                let msg = format!(":warning: The stream of `{audio}` dropped before the end, skipped");
+                if let Err(e) = self.channel_id.say(&self.http, msg).await {
+                    warn!("Failed to report the failure: {e}");
+                }
+            }
+            if let (Some(error), Some(substitute)) = (&error, &substitute) {
+                warn!("Failed to play `{audio}` from {}: {error}", audio.source().name());
+                let msg = format!(
+                    ":repeat: Failed to play `{audio}`\n{error}\nPlaying `{}` by {} instead ({:.0}% similar)",
+                    substitute.video.title, substitute.video.channel, substitute.score * 100.0,
+                );
+                if let Err(e) = self.channel_id.say(&self.http, msg).await {
+                    warn!("Failed to report the failure: {e}");
+                }
+            } else if let Some(error) = error {
+                warn!("Failed to play `{audio}` from {}: {error}", audio.source().name());
+                let mut msg = format!(":warning: Failed to play `{audio}`, skipped\n{error}");
+                if gave_up {
+                    msg += &format!(
+                        "\n{} songs failed in a row, stopped playing. The queue is kept, `play` something to continue",
+                        CONFIG.player.max_failures,
+                    );
+                }
+                if let Err(e) = self.channel_id.say(&self.http, msg).await {
+                    warn!("Failed to report the failure: {e}");
                 }
             }
         }
@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use tracing::warn;
+
+/// Settings read from the environment variables (or the `.env` file)
+pub static CONFIG: LazyLock<Config> = LazyLock::new(Config::from_env);
+
+#[derive(Debug)]
+pub struct Config {
+    pub library: LibraryConfig,
+    pub http: HttpConfig,
+    pub player: PlayerConfig,
+    pub catalog: CatalogConfig,
+    pub ytdlp: YtdlpConfig,
+    pub youtube: YoutubeConfig,
+    pub cache: CacheConfig,
+}
+
+#[derive(Debug)]
+pub struct LibraryConfig {
+    /// Root directory of the local music library, `MUSIC_LIBRARY`
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct HttpConfig {
+    /// Time limit of the requests reading links, feeds and music service APIs, `HTTP_TIMEOUT` in seconds
+    pub timeout: Duration,
+}
+
+#[derive(Debug)]
+pub struct PlayerConfig {
+    /// Songs failing in a row before the player stops, `MAX_TRACK_FAILURES`
+    pub max_failures: u32,
+    /// Times a song is resumed after its stream dropped, `MAX_TRACK_RESUMES`
+    pub max_resumes: u32,
+    /// Play another upload of the songs failing to load or play, `TRACK_FALLBACK`
+    pub fallback: bool,
+}
+
+/// The APIs used to read the links of music services, the base URLs can point to a mock
+#[derive(Debug)]
+pub struct CatalogConfig {
+    /// Spotify Web API, `SPOTIFY_API_URL`
+    pub spotify_api: String,
+    /// Spotify token endpoint, `SPOTIFY_AUTH_URL`
+    pub spotify_auth: String,
+    /// Client credentials, `SPOTIFY_CLIENT_ID` and `SPOTIFY_CLIENT_SECRET`
+    pub spotify_client: Option<(String, String)>,
+    /// iTunes lookup API for Apple Music links, `APPLE_MUSIC_API_URL`
+    pub apple_api: String,
+    /// Deezer API, `DEEZER_API_URL`
+    pub deezer_api: String,
+}
+
+/// How yt-dlp is run, for the metadata as well as the playback
+#[derive(Debug)]
+pub struct YtdlpConfig {
+    /// Path or name of the executable, `YTDLP_PATH`
+    pub binary: String,
+    /// Netscape cookies file, e.g. for age-restricted videos, `YTDLP_COOKIES`
+    pub cookies: Option<String>,
+    /// Proxy of yt-dlp and the audio streams, `YTDLP_PROXY`
+    pub proxy: Option<String>,
+    /// Format selector of the played and cached audio, `YTDLP_FORMAT`
+    pub format: String,
+    /// More options given to every run, separated by spaces, `YTDLP_ARGS`
+    pub extra_args: Vec<String>,
+    /// Time limit of a metadata fetch or search, `YTDLP_TIMEOUT` in seconds
+    pub timeout: Duration,
+    /// Maximum amount of processes running at the same time, `YTDLP_MAX_PROCESSES`
+    pub max_processes: usize,
+    /// Amount of retries of transient failures, `YTDLP_RETRIES`
+    pub retries: u32,
+}
+
+#[derive(Debug)]
+pub struct YoutubeConfig {
+    /// The HTTP API used instead of yt-dlp for the metadata and searches, yt-dlp is the fallback
+    pub api: Option<YoutubeApi>,
+    /// Time limit of an API request, yt-dlp is used when it runs out, `YOUTUBE_API_TIMEOUT` in seconds
+    pub api_timeout: Duration,
+}
+
+/// An Invidious or Piped instance, `YOUTUBE_API` and `YOUTUBE_API_URL`
+#[derive(Debug)]
+pub struct YoutubeApi {
+    pub kind: ApiKind,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKind {
+    Invidious,
+    Piped,
+}
+
+impl FromStr for ApiKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "invidious" => Ok(ApiKind::Invidious),
+            "piped" => Ok(ApiKind::Piped),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CacheConfig {
+    /// Directory of the cached data, `CACHE_DIR`
+    pub dir: PathBuf,
+    /// Lifetime of the cached metadata, `METADATA_CACHE_TTL` in hours
+    pub metadata_ttl: Duration,
+    /// Size limit of the cached metadata, `METADATA_CACHE_SIZE` in MiB, 0 disables the cache
+    pub metadata_max_size: u64,
+    /// Size limit of the downloaded audio, `AUDIO_CACHE_SIZE` in MiB, 0 (the default) disables the cache
+    pub audio_max_size: u64,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Config {
+            library: LibraryConfig {
+                path: var("MUSIC_LIBRARY").map(PathBuf::from),
+            },
+            http: HttpConfig {
+                timeout: Duration::from_secs(parse_var("HTTP_TIMEOUT", 15)),
+            },
+            player: PlayerConfig {
+                max_failures: parse_var("MAX_TRACK_FAILURES", 3).max(1),
+                max_resumes: parse_var("MAX_TRACK_RESUMES", 3),
+                fallback: parse_var("TRACK_FALLBACK", false),
+            },
+            catalog: CatalogConfig {
+                spotify_api: base_url("SPOTIFY_API_URL", "https://api.spotify.com/v1"),
+                spotify_auth: var("SPOTIFY_AUTH_URL")
+                    .unwrap_or_else(|| "https://accounts.spotify.com/api/token".to_string()),
+                spotify_client: var("SPOTIFY_CLIENT_ID").zip(var("SPOTIFY_CLIENT_SECRET")),
+                apple_api: base_url("APPLE_MUSIC_API_URL", "https://itunes.apple.com"),
+                deezer_api: base_url("DEEZER_API_URL", "https://api.deezer.com"),
+            },
+            ytdlp: YtdlpConfig {
+                binary: var("YTDLP_PATH").unwrap_or_else(|| "yt-dlp".to_string()),
+                cookies: var("YTDLP_COOKIES"),
+                proxy: var("YTDLP_PROXY"),
+                format: var("YTDLP_FORMAT").unwrap_or_else(|| "ba[abr>0][vcodec=none]/best".to_string()),
+                extra_args: var("YTDLP_ARGS")
+                    .map(|args| args.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default(),
+                timeout: Duration::from_secs(parse_var("YTDLP_TIMEOUT", 120)),
+                max_processes: parse_var("YTDLP_MAX_PROCESSES", 4).max(1),
+                retries: parse_var("YTDLP_RETRIES", 2),
+            },
+            youtube: YoutubeConfig {
+                api: var("YOUTUBE_API_URL").map(|_| YoutubeApi {
+                    kind: parse_var("YOUTUBE_API", ApiKind::Invidious),
+                    url: base_url("YOUTUBE_API_URL", ""),
+                }),
+                api_timeout: Duration::from_secs(parse_var("YOUTUBE_API_TIMEOUT", 5)),
+            },
+            cache: CacheConfig {
+                dir: var("CACHE_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("cache")),
+                metadata_ttl: Duration::from_secs(parse_var("METADATA_CACHE_TTL", 72) * 3600),
+                metadata_max_size: parse_var("METADATA_CACHE_SIZE", 64) * 1024 * 1024,
+                audio_max_size: parse_var("AUDIO_CACHE_SIZE", 0) * 1024 * 1024,
+            },
+        }
+    }
+}
+
+/// Read an environment variable, treat empty value as unset
+fn var(key: &str) -> Option<String> {
+    dotenvy::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Read a base URL, without the trailing slash
+fn base_url(key: &str, default: &str) -> String {
+    var(key).as_deref().unwrap_or(default).trim_end_matches('/').to_string()
+}
+
+/// Read a number or another parsable value, invalid ones are replaced by `default`
+fn parse_var<T: FromStr>(key: &str, default: T) -> T {
+    match var(key).map(|value| value.trim().parse()) {
+        Some(Ok(value)) => value,
+        Some(Err(_)) => {
+            warn!("Invalid value of `{key}`, using the default");
+            default
+        },
+        None => default,
+    }
+}
@@ -13,14 +13,19 @@ use songbird::SerenityInit;
 use tracing::error;
 use tracing::info;
 use tracing::instrument;
-use tracing_subscriber;
 
 mod command;
+mod config;
 mod structs;
 mod sources;
+use config::CONFIG;
+use sources::local::LIBRARY;
+use sources::audio_cache::AUDIO_CACHE;
+use sources::meta_cache::METADATA;
+use sources::runner;
 use structs::Data;
 
-static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| reqwest::Client::new());
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
 
 type Context<'a> = poise::Context<'a, Data, anyhow::Error>;
 
@@ -58,9 +63,15 @@ async fn main() -> anyhow::Result<()> {
             command::skip(),
             command::queue(),
             command::now_playing(),
+            command::rematch(),
+            command::announce(),
             command::cmd_loop(),
+            command::duplicates(),
             command::import(),
             command::export(),
+            command::podcast(),
+            command::library(),
+            command::cache(),
         ],
         prefix_options: poise::PrefixFrameworkOptions {
             prefix: Some("]".into()),
@@ -94,6 +105,25 @@ async fn main() -> anyhow::Result<()> {
             Box::pin(async move {
                 info!("Logged in as {}", ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                tokio::spawn(async {
+                    match runner::version().await {
+                        Ok(version) => info!("Using yt-dlp {version} from `{}`", CONFIG.ytdlp.binary),
+                        Err(e) => error!("yt-dlp is not usable, check `YTDLP_PATH`: {e}"),
+                    }
+                });
+                tokio::spawn(METADATA.run());
+                tokio::spawn(async {
+                    if let Err(e) = AUDIO_CACHE.load().await {
+                        error!("Failed to load the audio cache: {e}");
+                    }
+                });
+                if LIBRARY.root().is_some() {
+                    tokio::spawn(async {
+                        if let Err(e) = LIBRARY.rescan().await {
+                            error!("Library scan failed: {e}");
+                        }
+                    });
+                }
                 Ok(Data::new())
             })
         })
@@ -0,0 +1,156 @@
+use serenity::all::Attachment;
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::HttpRequest;
+use songbird::input::Input;
+
+use url::Url;
+
+use crate::CLIENT;
+use crate::structs::AudioLink;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::direct::DirectInfo;
+use super::direct::probe_url;
+
+/// Largest attachment accepted, in bytes
+const MAX_SIZE: u32 = 200 * 1024 * 1024;
+
+/// Audio files uploaded to Discord
+///
+/// The links of attachments expire after a while, so exported queues containing them
+/// may not load later
+pub struct Discord;
+
+#[async_trait]
+impl Source for Discord {
+    fn key(&self) -> &'static str {
+        "att"
+    }
+
+    fn name(&self) -> &'static str {
+        "Discord attachment"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(url.host_str(), Some("cdn.discordapp.com") | Some("media.discordapp.net"))
+            && url.path().starts_with("/attachments/")
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        let info = probe_url(url).await?
+            .ok_or_else(|| ParseError::NotAudio("The attachment is not an audio file, or the link has expired".to_string()))?;
+        Ok(Some(ParseResult::Single(AudioLink::new(AttachmentInfo::new(info, None)))))
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        let info = probe_url(&Url::parse(id)?).await?
+            .ok_or_else(|| anyhow::anyhow!("The attachment link has expired: {id}"))?;
+        Ok(AudioLink::new(AttachmentInfo::new(info, None)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentInfo {
+    pub url: String,
+    pub filename: String,
+    /// The embedded title, or the file name
+    pub title: String,
+    pub artist: Option<String>,
+    /// Duration in seconds, 0 if unknown
+    pub duration: u32,
+    /// Size in bytes, only known for attachments from messages
+    pub size: Option<u32>,
+}
+
+impl AttachmentInfo {
+    fn new(info: DirectInfo, size: Option<u32>) -> Self {
+        let filename = Url::parse(&info.url).ok()
+            .and_then(|url| url.path_segments()?.next_back().map(str::to_string))
+            .unwrap_or_else(|| info.title.clone());
+        AttachmentInfo {
+            url: info.url,
+            filename,
+            title: info.title,
+            artist: info.artist,
+            duration: info.duration,
+            size,
+        }
+    }
+
+    /// Validate an attachment of a message and read its tags
+    pub async fn from_attachment(attachment: &Attachment) -> Result<Self, ParseError> {
+        let is_audio = attachment.content_type.as_deref()
+            .is_some_and(|t| t.starts_with("audio/") || t.starts_with("application/ogg"));
+        if !is_audio {
+            return Err(ParseError::NotAudio(format!(
+                "Unsupported file type `{}`",
+                attachment.content_type.as_deref().unwrap_or("unknown"),
+            )));
+        }
+        if attachment.size > MAX_SIZE {
+            return Err(ParseError::TooLarge(format!("The file is too large, the limit is {} MiB", MAX_SIZE / 1024 / 1024)));
+        }
+        let url = Url::parse(&attachment.url)?;
+        let info = probe_url(&url).await?
+            .ok_or_else(|| ParseError::NotAudio("The attachment is not an audio file".to_string()))?;
+        let mut info = AttachmentInfo::new(info, Some(attachment.size));
+        info.filename = attachment.filename.clone();
+        if info.duration == 0 {
+            // Discord only provides the duration of voice messages
+            info.duration = attachment.duration_secs.unwrap_or_default() as u32;
+        }
+        Ok(info)
+    }
+}
+
+#[async_trait]
+impl Track for AttachmentInfo {
+    fn source(&self) -> &'static dyn Source {
+        &Discord
+    }
+
+    fn title(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// The signature in the query of attachment links changes over time
+    fn identity(&self) -> String {
+        format!("att:{}", self.url.split('?').next().unwrap_or_default())
+    }
+
+    fn input(&self) -> Input {
+        HttpRequest::new(CLIENT.clone(), self.url.clone()).into()
+    }
+
+    fn unload(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.title)
+            .url(&self.url)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new("Audio source from Discord attachment"));
+        if let Some(artist) = &self.artist {
+            m = m.field("Artist", artist, true);
+        }
+        if let Some(size) = self.size {
+            m = m.field("Size", format!("{:.1} MiB", size as f64 / 1024.0 / 1024.0), true);
+        }
+        m.field("File", format!("`{}`", self.filename), false)
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use tokio::sync::Semaphore;
+
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use crate::config::CONFIG;
+
+use super::runner;
+use super::youtube::YoutubeInfo;
+
+/// Audio of the Youtube videos played before, kept on disk so replays don't download it again
+pub static AUDIO_CACHE: LazyLock<AudioCache> = LazyLock::new(AudioCache::new);
+
+/// Time limit of a download, longer than a metadata fetch
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Downloads run one at a time, apart from the yt-dlp processes of the lookups and playback
+static DOWNLOAD_PERMITS: Semaphore = Semaphore::const_new(1);
+
+/// Downloaded files named `<video id>.<ext>`, the least recently played are removed when over the size limit
+pub struct AudioCache {
+    files: Mutex<HashMap<String, CachedFile>>,
+    /// Video IDs being downloaded
+    downloading: Mutex<HashSet<String>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+pub struct Stats {
+    pub files: usize,
+    /// Total size of the files in bytes
+    pub size: u64,
+}
+
+impl AudioCache {
+    fn new() -> Self {
+        AudioCache {
+            files: Mutex::new(HashMap::new()),
+            downloading: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        CONFIG.cache.audio_max_size > 0
+    }
+
+    fn dir(&self) -> PathBuf {
+        CONFIG.cache.dir.join("audio")
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.is_enabled() && self.files.lock().expect("Audio cache lock poisoned").contains_key(id)
+    }
+
+    /// Path of the cached audio, marks it as recently used
+    pub fn get(&self, id: &str) -> Option<PathBuf> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let now = SystemTime::now();
+        let path = {
+            let mut files = self.files.lock().expect("Audio cache lock poisoned");
+            let file = files.get_mut(id)?;
+            file.last_used = now;
+            file.path.clone()
+        };
+        if !path.exists() {
+            self.files.lock().expect("Audio cache lock poisoned").remove(id);
+            return None;
+        }
+        // The modification time keeps the order across restarts, set it off the async threads
+        tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || {
+                if let Err(e) = std::fs::File::options().write(true).open(&path).and_then(|f| f.set_modified(now)) {
+                    warn!("Failed to touch {}: {e}", path.display());
+                }
+            }
+        });
+        Some(path)
+    }
+
+    /// Download the audio of the video in the background, unless it's cached or being downloaded
+    pub fn fetch(&'static self, info: &YoutubeInfo) {
+        if !self.is_enabled() || self.contains(&info.id) {
+            return;
+        }
+        if !self.downloading.lock().expect("Audio cache lock poisoned").insert(info.id.clone()) {
+            return;
+        }
+        let id = info.id.clone();
+        let url = info.url();
+        tokio::spawn(async move {
+            if let Err(e) = self.download(&id, &url).await {
+                warn!("Failed to cache the audio of {id}: {e}");
+            }
+            self.downloading.lock().expect("Audio cache lock poisoned").remove(&id);
+        });
+    }
+
+    #[instrument(skip(self))]
+    async fn download(&self, id: &str, url: &str) -> Result<(), Error> {
+        let dir = self.dir();
+        tokio::task::spawn_blocking({
+            let dir = dir.clone();
+            move || std::fs::create_dir_all(dir)
+        }).await??;
+        let template = dir.join("%(id)s.%(ext)s");
+        let output = runner::run_with(&[
+            "-f",
+            &CONFIG.ytdlp.format,
+            "--no-playlist",
+            "--no-progress",
+            "--no-warning",
+            "-o",
+            &template.to_string_lossy(),
+            "--print",
+            "after_move:filepath",
+        ], url, DOWNLOAD_TIMEOUT, &DOWNLOAD_PERMITS).await?;
+        let path = PathBuf::from(output.trim());
+        let size = tokio::fs::metadata(&path).await?.len();
+        info!("Cached {} ({} KiB)", path.display(), size / 1024);
+
+        self.files.lock().expect("Audio cache lock poisoned").insert(id.to_string(), CachedFile {
+            path,
+            size,
+            last_used: SystemTime::now(),
+        });
+        self.evict();
+        Ok(())
+    }
+
+    /// Remove the least recently used files until the total size fits the limit
+    fn evict(&self) {
+        let mut files = self.files.lock().expect("Audio cache lock poisoned");
+        let mut size = files.values().map(|file| file.size).sum::<u64>();
+        if size <= CONFIG.cache.audio_max_size {
+            return;
+        }
+        let mut by_use = files.iter()
+            .map(|(id, file)| (file.last_used, id.clone()))
+            .collect::<Vec<_>>();
+        by_use.sort();
+        for (_, id) in by_use {
+            if size <= CONFIG.cache.audio_max_size {
+                break;
+            }
+            let Some(file) = files.remove(&id) else { continue };
+            // Songs on play keep reading the removed file until they end
+            if let Err(e) = std::fs::remove_file(&file.path) {
+                warn!("Failed to remove {}: {e}", file.path.display());
+            }
+            size -= file.size;
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        let files = self.files.lock().expect("Audio cache lock poisoned");
+        Stats {
+            files: files.len(),
+            size: files.values().map(|file| file.size).sum(),
+        }
+    }
+
+    /// Remove all the files, returns the amount removed
+    pub fn purge(&self) -> usize {
+        let mut files = self.files.lock().expect("Audio cache lock poisoned");
+        let count = files.len();
+        for (_, file) in files.drain() {
+            if let Err(e) = std::fs::remove_file(&file.path) {
+                warn!("Failed to remove {}: {e}", file.path.display());
+            }
+        }
+        count
+    }
+
+    /// Index the files downloaded by the last runs
+    #[instrument(skip(self))]
+    pub async fn load(&'static self) -> Result<usize, Error> {
+        if !self.is_enabled() {
+            return Ok(0);
+        }
+        let dir = self.dir();
+        let found = tokio::task::spawn_blocking(move || scan(&dir)).await??;
+        let count = {
+            let mut files = self.files.lock().expect("Audio cache lock poisoned");
+            for (id, file) in found {
+                files.entry(id).or_insert(file);
+            }
+            files.len()
+        };
+        info!("Audio cache loaded, {count} files");
+        // The limit may have been lowered since
+        self.evict();
+        Ok(count)
+    }
+}
+
+fn scan(dir: &Path) -> Result<Vec<(String, CachedFile)>, Error> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut files = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else { continue };
+        if !metadata.is_file() {
+            continue;
+        }
+        // Downloads interrupted by a restart
+        if path.extension().is_some_and(|ext| ext == "part" || ext == "ytdl") {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+        files.push((id.to_string(), CachedFile {
+            size: metadata.len(),
+            last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            path,
+        }));
+    }
+    Ok(files)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("std::io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Ytdlp(#[from] runner::Error),
+    #[error("Task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
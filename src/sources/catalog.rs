@@ -0,0 +1,854 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use serde_json::Value;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::Input;
+
+use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+
+use tracing::instrument;
+use tracing::warn;
+
+use url::Url;
+
+use crate::CLIENT;
+use crate::config::CONFIG;
+use crate::structs::AudioLink;
+use crate::structs::Metadata;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::fallback::Hint;
+use super::youtube;
+use super::youtube::SearchOptions;
+use super::youtube::YoutubeInfo;
+use super::youtube::search_yt;
+
+/// Matches below this confidence are flagged to the users
+pub const LOW_CONFIDENCE: f32 = 0.6;
+
+/// Amount of search results scored for each track
+const MATCH_CANDIDATES: usize = 10;
+
+/// Amount of scored candidates kept for `rematch`
+const KEPT_CANDIDATES: usize = 5;
+
+/// Pages of a Spotify album or playlist read at most
+const MAX_SPOTIFY_PAGES: usize = 10;
+
+/// Pages of a Deezer album or playlist read at most, each page after the first one has `DEEZER_PAGE_SIZE` tracks
+const MAX_DEEZER_PAGES: usize = 10;
+
+/// Tracks requested per page from Deezer
+const DEEZER_PAGE_SIZE: usize = 100;
+
+/// The fragment keeping the video picked with `rematch` in exported links, e.g. `#yt=<video id>`
+const PICKED_VIDEO: &str = "yt=";
+
+/// Amount of tracks matched at the same time
+const MATCH_CONCURRENCY: usize = 4;
+
+/// Words marking an alternative version, penalized unless the track title has them
+const VERSION_WORDS: &[&str] = &["live", "cover", "remix", "karaoke", "instrumental", "nightcore", "slowed", "sped", "acoustic"];
+
+/// The Spotify access token and its expiry
+static SPOTIFY_TOKEN: LazyLock<Mutex<Option<(String, Instant)>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Track, album and playlist links of Spotify, Apple Music and Deezer,
+/// each track is played from the best matching Youtube video
+pub struct Catalog;
+
+#[async_trait]
+impl Source for Catalog {
+    fn key(&self) -> &'static str {
+        "music"
+    }
+
+    fn name(&self) -> &'static str {
+        "Music service"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        Service::from_url(url).is_some()
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        let link = CatalogLink::parse(url).ok_or(Error::UnsupportedLink)?;
+        let Listing { tracks, title, omitted } = link.fetch().await?;
+        if let [track] = &tracks[..] {
+            let matched = match_track(link.service, track.clone()).await?;
+            return Ok(Some(ParseResult::Single(AudioLink::new(matched))));
+        }
+
+        let service = link.service;
+        let semaphore = Arc::new(Semaphore::new(MATCH_CONCURRENCY));
+        let handles = tracks.into_iter()
+            .map(|track| {
+                let semaphore = semaphore.clone();
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire().await.expect("Semaphore is never closed");
+                    match_track(service, track).await
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut list = vec![];
+        let mut missing = 0;
+        for handle in handles {
+            match handle.await {
+                Ok(Ok(matched)) => list.push(AudioLink::new(matched)),
+                Ok(Err(err)) => {
+                    warn!("Track skipped: {err}");
+                    missing += 1;
+                },
+                Err(err) => {
+                    warn!("Matching task failed: {err}");
+                    missing += 1;
+                },
+            }
+        }
+        let mut title = match missing {
+            0 => title,
+            n => format!("{title} ({n} tracks not found on Youtube)"),
+        };
+        if omitted > 0 {
+            title += &format!(" (only the first tracks were read, {omitted} more left out)");
+        }
+        Ok(Some(ParseResult::Multiple(list, Metadata { title })))
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        let mut url = Url::parse(id)?;
+        let picked = url.fragment()
+            .and_then(|fragment| fragment.strip_prefix(PICKED_VIDEO))
+            .map(str::to_string);
+        url.set_fragment(None);
+        let link = CatalogLink::parse(&url).ok_or(Error::UnsupportedLink)?;
+        let mut tracks = link.fetch().await?.tracks;
+        if tracks.len() != 1 {
+            anyhow::bail!("Not a single track: {id}");
+        }
+        let mut matched = match_track(link.service, tracks.remove(0)).await?;
+        if let Some(video_id) = picked {
+            let video = match matched.candidates.iter().find(|(video, _)| video.id == video_id) {
+                Some((video, _)) => video.clone(),
+                None => youtube::load(&video_id).await?,
+            };
+            matched.pick(video);
+        }
+        Ok(AudioLink::new(matched))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Spotify,
+    AppleMusic,
+    Deezer,
+}
+
+impl Service {
+    fn from_url(url: &Url) -> Option<Self> {
+        match url.host_str()? {
+            "open.spotify.com" => Some(Self::Spotify),
+            "music.apple.com" | "itunes.apple.com" => Some(Self::AppleMusic),
+            "www.deezer.com" | "deezer.com" => Some(Self::Deezer),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spotify => write!(f, "Spotify"),
+            Self::AppleMusic => write!(f, "Apple Music"),
+            Self::Deezer => write!(f, "Deezer"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkKind {
+    Track,
+    Album,
+    Playlist,
+}
+
+/// A parsed link of a music service
+#[derive(Debug)]
+struct CatalogLink {
+    service: Service,
+    kind: LinkKind,
+    id: String,
+    /// The storefront of Apple Music links, e.g. `us`
+    country: Option<String>,
+}
+
+impl CatalogLink {
+    fn parse(url: &Url) -> Option<Self> {
+        let service = Service::from_url(url)?;
+        let segments = url.path_segments()?.filter(|s| !s.is_empty()).collect::<Vec<_>>();
+        let kind_of = |segment: &str| match segment {
+            "track" | "song" => Some(LinkKind::Track),
+            "album" => Some(LinkKind::Album),
+            "playlist" => Some(LinkKind::Playlist),
+            _ => None,
+        };
+        // The kind is followed by the ID, optionally after a localized prefix like `intl-ja` or `fr`
+        let position = segments.iter().position(|segment| kind_of(segment).is_some())?;
+        let mut kind = kind_of(segments[position])?;
+        let mut id = match service {
+            // music.apple.com/us/album/<name>/<id>, the name is optional
+            Service::AppleMusic => segments.last()?.trim_start_matches("id").to_string(),
+            _ => segments.get(position + 1)?.to_string(),
+        };
+        let country = (service == Service::AppleMusic && position > 0).then(|| segments[0].to_string());
+        // Apple Music links a track as `album/<name>/<album id>?i=<track id>`
+        let track_id = url.query_pairs().find(|(key, _)| key == "i").map(|(_, id)| id.into_owned());
+        if let Some(track_id) = track_id.filter(|_| service == Service::AppleMusic) {
+            kind = LinkKind::Track;
+            id = track_id;
+        }
+        Some(CatalogLink { service, kind, id, country })
+    }
+
+    /// Read the tracks and the title of the album or playlist
+    #[instrument]
+    async fn fetch(&self) -> Result<Listing, Error> {
+        let listing = match self.service {
+            Service::Spotify => self.fetch_spotify(&CONFIG.catalog.spotify_api).await?,
+            Service::AppleMusic => self.fetch_apple(&CONFIG.catalog.apple_api).await?,
+            Service::Deezer => self.fetch_deezer(&CONFIG.catalog.deezer_api).await?,
+        };
+        if listing.tracks.is_empty() {
+            return Err(Error::NotFound);
+        }
+        Ok(listing)
+    }
+
+    async fn fetch_spotify(&self, api: &str) -> Result<Listing, Error> {
+        let path = match self.kind {
+            LinkKind::Track => "tracks",
+            LinkKind::Album => "albums",
+            LinkKind::Playlist => "playlists",
+        };
+        let token = spotify_token().await?;
+        let value = spotify_get(&format!("{}/{}/{}", api, path, self.id), token.as_deref()).await?;
+        let title = value["name"].as_str().unwrap_or("Unknown").to_string();
+        let read = |page: &Value| -> Vec<CatalogTrack> {
+            page["items"].as_array().into_iter().flatten()
+                .filter_map(|item| match self.kind {
+                    LinkKind::Playlist => CatalogTrack::from_spotify(&item["track"], None),
+                    _ => CatalogTrack::from_spotify(item, Some(&title)),
+                })
+                .collect()
+        };
+        if self.kind == LinkKind::Track {
+            let tracks = CatalogTrack::from_spotify(&value, None).into_iter().collect();
+            return Ok(Listing { tracks, title, omitted: 0 });
+        }
+
+        // Albums list 50 tracks per page, playlists 100
+        let total = value["tracks"]["total"].as_u64().unwrap_or_default() as usize;
+        let mut tracks = read(&value["tracks"]);
+        let mut read_items = value["tracks"]["items"].as_array().map_or(0, Vec::len);
+        let mut next = value["tracks"]["next"].as_str().map(str::to_string);
+        for _ in 1..MAX_SPOTIFY_PAGES {
+            let Some(url) = next.take() else { break };
+            let page = spotify_get(&url, token.as_deref()).await?;
+            tracks.extend(read(&page));
+            read_items += page["items"].as_array().map_or(0, Vec::len);
+            next = page["next"].as_str().map(str::to_string);
+        }
+        let omitted = match next {
+            Some(_) => total.saturating_sub(read_items),
+            None => 0,
+        };
+        Ok(Listing { tracks, title, omitted })
+    }
+
+    /// The public lookup API only knows the songs and the albums
+    async fn fetch_apple(&self, api: &str) -> Result<Listing, Error> {
+        if self.kind == LinkKind::Playlist {
+            return Err(Error::ApplePlaylist);
+        }
+        let mut query = vec![("id", self.id.as_str()), ("entity", "song")];
+        if let Some(country) = &self.country {
+            query.push(("country", country));
+        }
+        let request = CLIENT.get(format!("{}/lookup", api)).query(&query);
+        let value = get_json(request).await?;
+        let results = value["results"].as_array().cloned().unwrap_or_default();
+        let title = results.first()
+            .and_then(|item| item["collectionName"].as_str())
+            .unwrap_or("Unknown")
+            .to_string();
+        let tracks = results.iter()
+            .filter(|item| item["wrapperType"].as_str() == Some("track"))
+            .filter(|item| self.kind == LinkKind::Album || item["trackId"].as_u64().is_some_and(|id| id.to_string() == self.id))
+            .filter_map(|item| Some(CatalogTrack {
+                title: item["trackName"].as_str()?.to_string(),
+                artist: item["artistName"].as_str().unwrap_or_default().to_string(),
+                album: item["collectionName"].as_str().map(str::to_string),
+                duration: (item["trackTimeMillis"].as_u64().unwrap_or_default() / 1000) as u32,
+                link: item["trackViewUrl"].as_str()?.to_string(),
+            }))
+            .collect();
+        Ok(Listing { tracks, title, omitted: 0 })
+    }
+
+    async fn fetch_deezer(&self, api: &str) -> Result<Listing, Error> {
+        let path = match self.kind {
+            LinkKind::Track => "track",
+            LinkKind::Album => "album",
+            LinkKind::Playlist => "playlist",
+        };
+        let value = deezer_get(&format!("{}/{}/{}", api, path, self.id)).await?;
+        let title = value["title"].as_str().unwrap_or("Unknown").to_string();
+        let album = (self.kind == LinkKind::Album).then_some(title.as_str());
+        let read = |page: &Value| -> Vec<CatalogTrack> {
+            page["data"].as_array().into_iter().flatten()
+                .filter_map(|item| CatalogTrack::from_deezer(item, album))
+                .collect()
+        };
+        if self.kind == LinkKind::Track {
+            let tracks = CatalogTrack::from_deezer(&value, None).into_iter().collect();
+            return Ok(Listing { tracks, title, omitted: 0 });
+        }
+
+        // The first page comes with the album or playlist, the rest are read from its track list
+        let total = value["nb_tracks"].as_u64().unwrap_or_default() as usize;
+        let mut tracks = read(&value["tracks"]);
+        let mut read_items = value["tracks"]["data"].as_array().map_or(0, Vec::len);
+        let mut next = match value["tracks"]["next"].as_str() {
+            Some(url) => Some(url.to_string()),
+            None if read_items > 0 && read_items < total => {
+                Some(format!("{}/{}/{}/tracks?index={}&limit={}", api, path, self.id, read_items, DEEZER_PAGE_SIZE))
+            },
+            None => None,
+        };
+        for _ in 1..MAX_DEEZER_PAGES {
+            let Some(url) = next.take() else { break };
+            let page = deezer_get(&url).await?;
+            let items = page["data"].as_array().map_or(0, Vec::len);
+            if items == 0 {
+                break;
+            }
+            tracks.extend(read(&page));
+            read_items += items;
+            next = page["next"].as_str().map(str::to_string);
+        }
+        let omitted = match next {
+            Some(_) => total.saturating_sub(read_items),
+            None => 0,
+        };
+        Ok(Listing { tracks, title, omitted })
+    }
+}
+
+/// A track as listed by a music service
+#[derive(Debug, Clone)]
+pub struct CatalogTrack {
+    pub title: String,
+    /// The artists, joined with commas
+    pub artist: String,
+    pub album: Option<String>,
+    /// Duration in seconds, 0 if unknown
+    pub duration: u32,
+    /// The link of the track on the service
+    pub link: String,
+}
+
+impl CatalogTrack {
+    fn from_spotify(value: &Value, album: Option<&str>) -> Option<Self> {
+        let artist = value["artists"].as_array().into_iter().flatten()
+            .filter_map(|artist| artist["name"].as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(CatalogTrack {
+            title: value["name"].as_str()?.to_string(),
+            artist,
+            album: album.or(value["album"]["name"].as_str()).map(str::to_string),
+            duration: (value["duration_ms"].as_u64().unwrap_or_default() / 1000) as u32,
+            link: value["external_urls"]["spotify"].as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("https://open.spotify.com/track/{}", value["id"].as_str().unwrap_or_default())),
+        })
+    }
+
+    fn from_deezer(value: &Value, album: Option<&str>) -> Option<Self> {
+        Some(CatalogTrack {
+            title: value["title"].as_str()?.to_string(),
+            artist: value["artist"]["name"].as_str().unwrap_or_default().to_string(),
+            album: album.or(value["album"]["title"].as_str()).map(str::to_string),
+            duration: value["duration"].as_u64().unwrap_or_default() as u32,
+            link: value["link"].as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("https://www.deezer.com/track/{}", value["id"])),
+        })
+    }
+}
+
+/// The tracks read from a link, and the title of the album or playlist
+struct Listing {
+    tracks: Vec<CatalogTrack>,
+    title: String,
+    /// Tracks past the page limit which weren't read, 0 when the list is complete
+    omitted: usize,
+}
+
+/// A music service track and the Youtube video played for it
+#[derive(Debug, Clone)]
+pub struct MatchedTrack {
+    pub service: Service,
+    pub track: CatalogTrack,
+    pub video: YoutubeInfo,
+    /// How close the video is to the track, from 0 to 1, set to 1 once picked by a user
+    pub confidence: f32,
+    /// The best scored search results, for `rematch`
+    pub candidates: Vec<(YoutubeInfo, f32)>,
+    /// The video was picked by a user, kept when the queue is exported
+    pub picked: bool,
+}
+
+impl MatchedTrack {
+    /// Replace the matched video with one picked by a user
+    pub fn pick(&mut self, video: YoutubeInfo) {
+        self.video = video;
+        self.confidence = 1.0;
+        self.picked = true;
+    }
+}
+
+#[async_trait]
+impl Track for MatchedTrack {
+    fn source(&self) -> &'static dyn Source {
+        &Catalog
+    }
+
+    fn title(&self) -> String {
+        format!("{} - {}", self.track.artist, self.track.title)
+    }
+
+    fn duration(&self) -> u32 {
+        self.video.duration
+    }
+
+    fn video(&self) -> Option<&YoutubeInfo> {
+        Some(&self.video)
+    }
+
+    /// Flag the tracks which may be matched to a wrong video
+    fn note(&self) -> Option<String> {
+        (self.confidence < LOW_CONFIDENCE).then(|| format!(":warning: {:.0}% match", self.confidence * 100.0))
+    }
+
+    fn hint(&self) -> Option<Hint> {
+        Some(Hint {
+            title: self.track.title.clone(),
+            channel: Some(self.track.artist.clone()),
+            duration: self.track.duration,
+            link: Some(self.track.link.clone()),
+            video_id: Some(self.video.id.clone()),
+        })
+    }
+
+    fn input(&self) -> Input {
+        self.video.input()
+    }
+
+    /// The link of the track, with the video if it was picked by a user
+    fn unload(&self) -> String {
+        match self.picked {
+            true => format!("{}#{}{}", self.track.link, PICKED_VIDEO, self.video.id),
+            false => self.track.link.clone(),
+        }
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.track.title)
+            .url(&self.track.link)
+            .field("Artist", &self.track.artist, true)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new(format!("Audio source from {} via Youtube", self.service)));
+        if let Some(album) = &self.track.album {
+            m = m.field("Album", album, true);
+        }
+        let mut confidence = format!("{:.0}%", self.confidence * 100.0);
+        if self.confidence < LOW_CONFIDENCE {
+            confidence += " :warning: use `rematch 0` to fix it";
+        }
+        m.field("Played from", format!("[{}]({})", self.video.title, self.video.url()), false)
+            .field("Match confidence", confidence, false)
+    }
+}
+
+/// Search Youtube for the track and keep the best scored result
+#[instrument]
+pub async fn match_track(service: Service, track: CatalogTrack) -> Result<MatchedTrack, Error> {
+    let query = format!("{} {}", track.artist, track.title);
+    let results = search_yt(&query, &SearchOptions::default()).await
+        .map_err(|err| Error::Search(err.to_string()))?;
+    let mut candidates = results.into_iter()
+        .take(MATCH_CANDIDATES)
+        .map(|video| {
+            let score = similarity(&track, &video);
+            (video, score)
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    candidates.truncate(KEPT_CANDIDATES);
+    let (video, confidence) = candidates.first().cloned().ok_or_else(|| Error::NoMatch(track.title.clone()))?;
+    Ok(MatchedTrack { service, track, video, confidence, candidates, picked: false })
+}
+
+/// Score a video against a track by the title, the artist and the duration
+pub fn similarity(track: &CatalogTrack, video: &YoutubeInfo) -> f32 {
+    let video_title = words(&video.title);
+    let video_all = video_title.union(&words(&video.channel)).cloned().collect::<HashSet<_>>();
+    let title_words = words(&track.title);
+    let artist_words = words(&track.artist);
+
+    let coverage = |wanted: &HashSet<String>, found: &HashSet<String>| match wanted.len() {
+        0 => 1.0,
+        n => wanted.intersection(found).count() as f32 / n as f32,
+    };
+    let title_score = coverage(&title_words, &video_title);
+    let artist_score = coverage(&artist_words, &video_all);
+    let duration_score = match (track.duration, video.duration) {
+        (0, _) | (_, 0) => 0.5,
+        (a, b) => (1.0 - a.abs_diff(b).saturating_sub(3) as f32 / 30.0).max(0.0),
+    };
+
+    let mut score = 0.5 * title_score + 0.25 * artist_score + 0.25 * duration_score;
+    for word in VERSION_WORDS {
+        if video_title.contains(*word) && !title_words.contains(*word) {
+            score *= 0.8;
+        }
+    }
+    score
+}
+
+/// Lowercase words, punctuation is ignored
+fn words(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Get a token with the client credentials, `None` when they are not configured
+async fn spotify_token() -> Result<Option<String>, Error> {
+    let Some((id, secret)) = &CONFIG.catalog.spotify_client else {
+        return Ok(None);
+    };
+    let mut token = SPOTIFY_TOKEN.lock().await;
+    if let Some((value, expiry)) = token.as_ref() {
+        if Instant::now() < *expiry {
+            return Ok(Some(value.clone()));
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        access_token: String,
+        expires_in: u64,
+    }
+    let response = CLIENT.post(&CONFIG.catalog.spotify_auth)
+        .basic_auth(id, Some(secret))
+        .form(&[("grant_type", "client_credentials")])
+        .timeout(CONFIG.http.timeout)
+        .send().await?
+        .error_for_status()?
+        .bytes().await?;
+    let response = serde_json::from_slice::<TokenResponse>(&response)?;
+    // Renew a minute early
+    let expiry = Instant::now() + Duration::from_secs(response.expires_in.saturating_sub(60));
+    *token = Some((response.access_token.clone(), expiry));
+    Ok(Some(response.access_token))
+}
+
+/// A Spotify API request, authorized when client credentials are configured
+async fn spotify_get(url: &str, token: Option<&str>) -> Result<Value, Error> {
+    let mut request = CLIENT.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    get_json(request).await
+}
+
+/// A Deezer API request, which reports the errors in the body
+async fn deezer_get(url: &str) -> Result<Value, Error> {
+    let value = get_json(CLIENT.get(url)).await?;
+    if value.get("error").is_some() {
+        return Err(Error::NotFound);
+    }
+    Ok(value)
+}
+
+async fn get_json(request: reqwest::RequestBuilder) -> Result<Value, Error> {
+    let response = request.timeout(CONFIG.http.timeout).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::NotFound);
+    }
+    let data = response.error_for_status()?.bytes().await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Unsupported music service link")]
+    UnsupportedLink,
+    #[error("Apple Music playlists can't be read, only its songs and albums")]
+    ApplePlaylist,
+    #[error("The track, album or playlist was not found")]
+    NotFound,
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Youtube search failed: {0}")]
+    Search(String),
+    #[error("No video found for `{0}`")]
+    NoMatch(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mock_server::MockServer;
+
+    fn track(title: &str, artist: &str, duration: u32) -> CatalogTrack {
+        CatalogTrack {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: None,
+            duration,
+            link: "https://open.spotify.com/track/1".to_string(),
+        }
+    }
+
+    fn video(title: &str, channel: &str, duration: u32) -> YoutubeInfo {
+        YoutubeInfo {
+            id: "dQw4w9WgXcQ".to_string(),
+            title: title.to_string(),
+            description: None,
+            channel: channel.to_string(),
+            channel_url: String::new(),
+            duration,
+            playlist: None,
+        }
+    }
+
+    fn link(kind: LinkKind, id: &str) -> CatalogLink {
+        let service = Service::Deezer;
+        CatalogLink { service, kind, id: id.to_string(), country: None }
+    }
+
+    #[test]
+    fn parse_link() {
+        use LinkKind::*;
+        use Service::*;
+        let cases = [
+            ("https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc", Some((Spotify, Track, "4uLU6hMCjMI75M1A2tKUQC", None))),
+            ("https://open.spotify.com/intl-ja/album/1DFixLWuPkv3KT3TnV35m3", Some((Spotify, Album, "1DFixLWuPkv3KT3TnV35m3", None))),
+            ("https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M", Some((Spotify, Playlist, "37i9dQZF1DXcBWIGoYBM5M", None))),
+            ("https://music.apple.com/us/album/never-gonna-give-you-up/1559523357", Some((AppleMusic, Album, "1559523357", Some("us")))),
+            ("https://music.apple.com/jp/album/never-gonna-give-you-up/1559523357?i=1559523359", Some((AppleMusic, Track, "1559523359", Some("jp")))),
+            ("https://music.apple.com/us/song/never-gonna-give-you-up/1559523359", Some((AppleMusic, Track, "1559523359", Some("us")))),
+            ("https://itunes.apple.com/album/id1559523357", Some((AppleMusic, Album, "1559523357", None))),
+            ("https://music.apple.com/us/playlist/todays-hits/pl.f4d106fed2bd41149aaacabb233eb5eb", Some((AppleMusic, Playlist, "pl.f4d106fed2bd41149aaacabb233eb5eb", Some("us")))),
+            ("https://www.deezer.com/fr/track/3135556", Some((Deezer, Track, "3135556", None))),
+            ("https://deezer.com/album/302127", Some((Deezer, Album, "302127", None))),
+            // The picked video of exported links is not part of the ID
+            ("https://www.deezer.com/track/3135556#yt=dQw4w9WgXcQ", Some((Deezer, Track, "3135556", None))),
+            ("https://open.spotify.com/artist/0gxyHStUsqpMadRV0Di1Qt", None),
+            ("https://open.spotify.com/track/", None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ", None),
+        ];
+        for (url, expected) in cases {
+            let link = CatalogLink::parse(&Url::parse(url).unwrap());
+            let link = link.as_ref().map(|l| (l.service, l.kind, l.id.as_str(), l.country.as_deref()));
+            assert_eq!(link, expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn score() {
+        let wanted = track("Never Gonna Give You Up", "Rick Astley", 213);
+        let official = similarity(&wanted, &video("Rick Astley - Never Gonna Give You Up (Official Music Video)", "Rick Astley", 213));
+        // The artist found in the channel name counts as well
+        let topic = similarity(&wanted, &video("Never Gonna Give You Up", "Rick Astley - Topic", 214));
+        let cover = similarity(&wanted, &video("Never Gonna Give You Up (Cover)", "Some Band", 200));
+        let live = similarity(&wanted, &video("Rick Astley - Never Gonna Give You Up (Live)", "Rick Astley", 213));
+        let other = similarity(&wanted, &video("Together Forever", "Rick Astley", 205));
+        let unknown_duration = similarity(&wanted, &video("Rick Astley - Never Gonna Give You Up", "Rick Astley", 0));
+        assert!((official - 1.0).abs() < 1e-6, "{official}");
+        assert!((topic - 1.0).abs() < 1e-6, "{topic}");
+        assert!((unknown_duration - 0.875).abs() < 1e-6, "{unknown_duration}");
+        assert!((live - 0.8).abs() < 1e-6, "{live}");
+        assert!(cover < LOW_CONFIDENCE, "{cover}");
+        assert!(other < LOW_CONFIDENCE, "{other}");
+        // Far off durations score nothing, punctuation and case are ignored
+        let long = similarity(&track("Don't Stop", "A-ha", 100), &video("a ha - DON T STOP", "x", 200));
+        assert!((long - 0.75).abs() < 1e-6, "{long}");
+    }
+
+    #[test]
+    fn picked_video() {
+        let wanted = track("Never Gonna Give You Up", "Rick Astley", 213);
+        let mut matched = MatchedTrack {
+            service: Service::Spotify,
+            track: wanted,
+            video: video("Never Gonna Give You Up", "Rick Astley", 213),
+            confidence: 0.5,
+            candidates: vec![],
+            picked: false,
+        };
+        assert_eq!(matched.unload(), "https://open.spotify.com/track/1");
+        let mut other = video("Never Gonna Give You Up (Remastered)", "Rick Astley", 213);
+        other.id = "AyOqGRjVtls".to_string();
+        matched.pick(other);
+        assert_eq!(matched.unload(), "https://open.spotify.com/track/1#yt=AyOqGRjVtls");
+        assert_eq!(matched.confidence, 1.0);
+    }
+
+    const DEEZER_TRACK: &str = r#"{"id": 1, "title": "Song {n}", "duration": 180, "link": "https://www.deezer.com/track/{n}", "artist": {"name": "Artist"}, "album": {"title": "Album"}}"#;
+
+    fn deezer_page(range: std::ops::Range<usize>, next: Option<&str>) -> String {
+        let data = range.map(|n| DEEZER_TRACK.replace("{n}", &n.to_string())).collect::<Vec<_>>().join(",");
+        match next {
+            Some(next) => format!(r#"{{"data": [{data}], "next": "{next}"}}"#),
+            None => format!(r#"{{"data": [{data}]}}"#),
+        }
+    }
+
+    #[tokio::test]
+    async fn deezer_next_pages() {
+        let playlist = format!(
+            r#"{{"title": "Mix", "nb_tracks": 5, "tracks": {}}}"#,
+            deezer_page(0..2, Some("{url}/playlist/7/tracks?index=2")),
+        );
+        let server = MockServer::start(&[
+            ("/playlist/7", 200, &playlist),
+            ("/playlist/7/tracks?index=2", 200, &deezer_page(2..4, Some("{url}/playlist/7/tracks?index=4"))),
+            ("/playlist/7/tracks?index=4", 200, &deezer_page(4..5, None)),
+        ]).await;
+        let listing = link(LinkKind::Playlist, "7").fetch_deezer(&server.url).await.unwrap();
+        assert_eq!(listing.title, "Mix");
+        assert_eq!(listing.omitted, 0);
+        let titles = listing.tracks.iter().map(|t| t.title.as_str()).collect::<Vec<_>>();
+        assert_eq!(titles, ["Song 0", "Song 1", "Song 2", "Song 3", "Song 4"]);
+        // Playlist tracks keep their own album
+        assert_eq!(listing.tracks[0].album.as_deref(), Some("Album"));
+        assert_eq!(listing.tracks[4].link, "https://www.deezer.com/track/4");
+    }
+
+    #[tokio::test]
+    async fn deezer_track_list() {
+        // Without `next`, the rest is read from the track list of the album
+        let album = format!(r#"{{"title": "Record", "nb_tracks": 250, "tracks": {}}}"#, deezer_page(0..25, None));
+        let page = deezer_page(25..125, Some("{url}/album/9/tracks?index=125&limit=100"));
+        let server = MockServer::start(&[
+            ("/album/9", 200, &album),
+            ("/album/9/tracks?index=25&limit=100", 200, &page),
+            ("/album/9/tracks?index=125&limit=100", 200, r#"{"data": []}"#),
+        ]).await;
+        let listing = link(LinkKind::Album, "9").fetch_deezer(&server.url).await.unwrap();
+        assert_eq!(listing.tracks.len(), 125);
+        assert_eq!(listing.tracks[124].title, "Song 124");
+        assert_eq!(listing.tracks[0].album.as_deref(), Some("Record"));
+        // An empty page ends the list, whatever the total says
+        assert_eq!(listing.omitted, 0);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn deezer_errors() {
+        let server = MockServer::start(&[
+            ("/track/1", 200, r#"{"error": {"type": "DataException", "message": "no data", "code": 800}}"#),
+            ("/track/2", 500, "{}"),
+        ]).await;
+        let err = link(LinkKind::Track, "1").fetch_deezer(&server.url).await.err();
+        assert!(matches!(err, Some(Error::NotFound)), "{err:?}");
+        let err = link(LinkKind::Track, "2").fetch_deezer(&server.url).await.err();
+        assert!(matches!(err, Some(Error::Request(_))), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn spotify_next_pages() {
+        let item = |n: usize| format!(
+            r#"{{"track": {{"id": "{n}", "name": "Song {n}", "duration_ms": 200000, "artists": [{{"name": "A"}}, {{"name": "B"}}], "album": {{"name": "Album"}}}}}}"#,
+        );
+        let items = |range: std::ops::Range<usize>| range.map(item).collect::<Vec<_>>().join(",");
+        let playlist = format!(
+            r#"{{"name": "List", "tracks": {{"total": 4, "items": [{}], "next": "{{url}}/playlists/p/tracks?offset=2"}}}}"#,
+            items(0..2),
+        );
+        let page = format!(r#"{{"items": [{}], "next": null}}"#, items(2..4));
+        let server = MockServer::start(&[
+            ("/playlists/p", 200, &playlist),
+            ("/playlists/p/tracks?offset=2", 200, &page),
+        ]).await;
+        let link = CatalogLink { service: Service::Spotify, kind: LinkKind::Playlist, id: "p".to_string(), country: None };
+        let listing = link.fetch_spotify(&server.url).await.unwrap();
+        assert_eq!(listing.title, "List");
+        assert_eq!(listing.omitted, 0);
+        assert_eq!(listing.tracks.len(), 4);
+        let track = &listing.tracks[3];
+        assert_eq!((track.title.as_str(), track.artist.as_str(), track.duration), ("Song 3", "A, B", 200));
+        assert_eq!(track.link, "https://open.spotify.com/track/3");
+    }
+
+    #[tokio::test]
+    async fn apple_album() {
+        let album = r#"{"results": [
+            {"wrapperType": "collection", "collectionName": "Whenever You Need Somebody"},
+            {"wrapperType": "track", "trackId": 11, "trackName": "Never Gonna Give You Up", "artistName": "Rick Astley",
+             "collectionName": "Whenever You Need Somebody", "trackTimeMillis": 213000, "trackViewUrl": "https://music.apple.com/us/song/11"},
+            {"wrapperType": "track", "trackId": 12, "trackName": "Whenever You Need Somebody", "artistName": "Rick Astley",
+             "collectionName": "Whenever You Need Somebody", "trackTimeMillis": 234000, "trackViewUrl": "https://music.apple.com/us/song/12"}
+        ]}"#;
+        let server = MockServer::start(&[
+            ("/lookup?id=10&entity=song&country=us", 200, album),
+            ("/lookup?id=12&entity=song&country=us", 200, album),
+        ]).await;
+        let mut link = CatalogLink {
+            service: Service::AppleMusic,
+            kind: LinkKind::Album,
+            id: "10".to_string(),
+            country: Some("us".to_string()),
+        };
+        let listing = link.fetch_apple(&server.url).await.unwrap();
+        assert_eq!(listing.title, "Whenever You Need Somebody");
+        assert_eq!(listing.tracks.len(), 2);
+        assert_eq!(listing.tracks[0].duration, 213);
+
+        // A song is looked up with its album, only the song is kept
+        link.kind = LinkKind::Track;
+        link.id = "12".to_string();
+        let listing = link.fetch_apple(&server.url).await.unwrap();
+        let titles = listing.tracks.iter().map(|t| t.title.as_str()).collect::<Vec<_>>();
+        assert_eq!(titles, ["Whenever You Need Somebody"]);
+
+        link.kind = LinkKind::Playlist;
+        let err = link.fetch_apple(&server.url).await.err();
+        assert!(matches!(err, Some(Error::ApplePlaylist)), "{err:?}");
+    }
+}
@@ -0,0 +1,386 @@
+use reqwest::StatusCode;
+use reqwest::header::CONTENT_DISPOSITION;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::RANGE;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::HttpRequest;
+use songbird::input::Input;
+
+use tokio::sync::OnceCell;
+
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use url::Url;
+
+use crate::CLIENT;
+use crate::config::CONFIG;
+use crate::structs::AudioLink;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::fallback::Hint;
+use super::playlist::PlaylistFormat;
+use super::podcast;
+use super::probe::AudioTags;
+use super::probe::Prefix;
+use super::probe::probe;
+
+/// Amount of chapters listed by `now_playing`
+const CHAPTER_LIST_SIZE: usize = 10;
+
+/// Amount of bytes downloaded to read the tags
+const PROBE_SIZE: usize = 256 * 1024;
+
+/// Amount of bytes downloaded at most, when a large ID3 tag (e.g. with cover art) comes first
+const MAX_PROBE_SIZE: usize = 4 * 1024 * 1024;
+
+/// File extensions accepted when the server doesn't send a proper content type
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "oga", "opus", "flac", "wav", "m4a", "aac", "webm"];
+
+/// Audio files served over HTTP, e.g. `https://example.com/song.mp3`
+pub struct Direct;
+
+#[async_trait]
+impl Source for Direct {
+    fn key(&self) -> &'static str {
+        "http"
+    }
+
+    fn name(&self) -> &'static str {
+        "Web"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        Ok(probe_url(url).await?.map(|info| ParseResult::Single(AudioLink::new(info))))
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        let url = Url::parse(id)?;
+        probe_url(&url).await?
+            .map(AudioLink::new)
+            .ok_or_else(|| anyhow::anyhow!("Not an audio file: {id}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectInfo {
+    pub url: String,
+    /// The embedded title, or the file name
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Duration in seconds, 0 if unknown
+    pub duration: u32,
+    pub content_type: Option<String>,
+    /// Publish date of podcast episodes
+    pub published: Option<String>,
+    /// Chapters listed in the podcast feed, or fetched from `chapters_url` the first time they're shown
+    pub chapters: OnceCell<Vec<Chapter>>,
+    /// Link of the JSON chapters file of podcast episodes
+    pub chapters_url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    /// Start time in seconds
+    pub start: u32,
+    pub title: String,
+}
+
+#[async_trait]
+impl Track for DirectInfo {
+    fn source(&self) -> &'static dyn Source {
+        &Direct
+    }
+
+    fn title(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    fn hint(&self) -> Option<Hint> {
+        Some(Hint {
+            title: self.title.clone(),
+            channel: self.artist.clone(),
+            duration: self.duration,
+            link: Some(self.url.clone()),
+            video_id: None,
+        })
+    }
+
+    fn input(&self) -> Input {
+        HttpRequest::new(CLIENT.clone(), self.url.clone()).into()
+    }
+
+    fn unload(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn embed(&self, position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.title)
+            .url(&self.url)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new("Audio source from the web"));
+        if let Some(artist) = &self.artist {
+            m = m.field("Artist", artist, true);
+        }
+        if let Some(album) = &self.album {
+            m = m.field("Album", album, true);
+        }
+        if let Some(content_type) = &self.content_type {
+            m = m.field("Type", format!("`{content_type}`"), true);
+        }
+        if let Some(published) = &self.published {
+            m = m.field("Published", published, true);
+        }
+        // Failures are not cached, the chapters are fetched again next time
+        let chapters = self.chapters.get_or_try_init(|| async {
+            match &self.chapters_url {
+                Some(url) => podcast::fetch_chapters(url).await,
+                None => Ok(vec![]),
+            }
+        }).await;
+        match chapters {
+            Ok(chapters) if !chapters.is_empty() => {
+                m = m.field("Chapters", chapter_list(chapters, position), false);
+            },
+            Ok(_) => {},
+            Err(e) => warn!("Failed to fetch the chapters: {e}"),
+        }
+        m
+    }
+}
+
+/// List the chapters around the one on play, which is marked
+fn chapter_list(chapters: &[Chapter], position: Option<u32>) -> String {
+    let current = position.and_then(|position| chapters.iter().rposition(|chapter| chapter.start <= position));
+    chapters.iter()
+        .enumerate()
+        .skip(current.unwrap_or_default().saturating_sub(2))
+        .take(CHAPTER_LIST_SIZE)
+        .map(|(i, chapter)| {
+            let line = format!("`{}:{:02}` {}", chapter.start / 60, chapter.start % 60, chapter.title);
+            match Some(i) == current {
+                true => format!(":arrow_forward: **{line}**"),
+                false => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Download the beginning of the file to check the content type and read the tags,
+/// returns `None` if it's not an audio file
+#[instrument]
+pub async fn probe_url(url: &Url) -> Result<Option<DirectInfo>, ParseError> {
+    let mut response = CLIENT.get(url.clone())
+        .header(RANGE, format!("bytes=0-{}", MAX_PROBE_SIZE - 1))
+        .timeout(CONFIG.http.timeout)
+        .send().await?
+        .error_for_status()?;
+
+    let headers = response.headers();
+    let content_type = headers.get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or_default().trim().to_lowercase());
+    let file_name = headers.get(CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(disposition_file_name)
+        .or_else(|| url_file_name(url));
+    let extension = file_name.as_deref()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase());
+
+    let is_audio = match content_type.as_deref() {
+        Some(t) if PlaylistFormat::from_content_type(t).is_some() => false,
+        Some(t) if t.starts_with("audio/") => true,
+        Some("application/ogg") => true,
+        Some("application/octet-stream") | None => {
+            extension.as_deref().is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext))
+        },
+        _ => false,
+    };
+    if !is_audio {
+        return Ok(None);
+    }
+
+    let total = total_size(&response);
+    let mut data = Vec::with_capacity(PROBE_SIZE);
+    let mut wanted = PROBE_SIZE;
+    while data.len() < wanted {
+        match response.chunk().await? {
+            Some(chunk) => data.extend_from_slice(&chunk),
+            None => break,
+        }
+        // Keep reading past the ID3 tag, the audio frames after it tell the format
+        if let Some(tag) = id3_size(&data) {
+            wanted = (tag + PROBE_SIZE).min(MAX_PROBE_SIZE);
+        }
+    }
+    let tags = match id3_size(&data) {
+        Some(tag) if tag >= data.len() => {
+            info!("The ID3 tag of {url} is larger than {MAX_PROBE_SIZE} bytes, using the file name");
+            AudioTags::default()
+        },
+        _ => {
+            let is_mp3 = content_type.as_deref() == Some("audio/mpeg") || extension.as_deref() == Some("mp3");
+            let source = Prefix::new(data, total, is_mp3);
+            tokio::task::spawn_blocking(move || probe(Box::new(source), extension.as_deref()))
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+        },
+    };
+
+    Ok(Some(DirectInfo {
+        url: url.to_string(),
+        title: tags.title.or(file_name).unwrap_or_else(|| url.to_string()),
+        artist: tags.artist,
+        album: tags.album,
+        duration: tags.duration.unwrap_or_default(),
+        content_type,
+        published: None,
+        chapters: OnceCell::new(),
+        chapters_url: None,
+    }))
+}
+
+/// Size of the whole file, from `Content-Range` when the range was served, otherwise `Content-Length`
+fn total_size(response: &reqwest::Response) -> Option<u64> {
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => response.headers().get(CONTENT_RANGE)?
+            .to_str().ok()?
+            .rsplit_once('/')?
+            .1
+            .parse()
+            .ok(),
+        _ => response.content_length(),
+    }
+}
+
+/// Size of the ID3v2 tag at the start of the data, header and footer included
+fn id3_size(data: &[u8]) -> Option<usize> {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return None;
+    }
+    // Syncsafe integer, 7 bits per byte
+    let size = data[6..10].iter().fold(0, |size, byte| size << 7 | (*byte & 0x7f) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + size + footer)
+}
+
+/// The last path segment of the URL, percent-decoded
+fn url_file_name(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.next_back()?;
+    let name = urlencoding::decode(segment).ok()?.into_owned();
+    (!name.is_empty()).then_some(name)
+}
+
+/// The file name of a `Content-Disposition` header, `filename*` (RFC 5987) is preferred over `filename`
+fn disposition_file_name(header: &str) -> Option<String> {
+    let mut plain = None;
+    for part in header.split(';') {
+        let Some((key, value)) = part.split_once('=') else { continue };
+        match key.trim().to_lowercase().as_str() {
+            "filename*" => if let Some(name) = extended_value(value.trim()) {
+                return Some(name);
+            },
+            "filename" if plain.is_none() => {
+                plain = Some(value.trim().trim_matches('"').to_string()).filter(|name| !name.is_empty());
+            },
+            _ => {},
+        }
+    }
+    plain
+}
+
+/// Decode an RFC 5987 value, `<charset>'<language>'<percent-encoded value>`
+fn extended_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let bytes = urlencoding::decode_binary(encoded.as_bytes());
+    let name = match charset.to_lowercase().as_str() {
+        "utf-8" => String::from_utf8(bytes.into_owned()).ok()?,
+        // Each byte is the code point of the same value
+        "iso-8859-1" => bytes.iter().map(|byte| *byte as char).collect(),
+        _ => return None,
+    };
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id3() {
+        let header = |flags: u8, size: [u8; 4]| {
+            let mut data = b"ID3\x04\x00".to_vec();
+            data.push(flags);
+            data.extend(size);
+            data
+        };
+        let cases = [
+            (header(0, [0, 0, 0, 0]), Some(10)),
+            (header(0, [0, 0, 0x02, 0x01]), Some(10 + 257)),
+            (header(0, [0x7f, 0x7f, 0x7f, 0x7f]), Some(10 + 0x0fff_ffff)),
+            // The high bit of each byte is not part of the size
+            (header(0, [0x80, 0x80, 0x81, 0x80]), Some(10 + 128)),
+            (header(0x10, [0, 0, 0, 100]), Some(10 + 100 + 10)),
+            // Truncated headers
+            (header(0, [0, 0, 0, 100])[..9].to_vec(), None),
+            (b"ID3".to_vec(), None),
+            (vec![], None),
+            (b"RIFF\x04\x00\x00\x00\x00\x00\x00".to_vec(), None),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(id3_size(&data), expected, "{data:?}");
+        }
+    }
+
+    #[test]
+    fn disposition() {
+        let cases = [
+            ("attachment; filename=song.mp3", Some("song.mp3")),
+            ("attachment; filename=\"my song.mp3\"", Some("my song.mp3")),
+            ("inline; FileName=\"Song.ogg\"; size=123", Some("Song.ogg")),
+            ("attachment; filename*=UTF-8''na%C3%AFve%20song.mp3", Some("naïve song.mp3")),
+            ("attachment; filename*=utf-8'en'%E6%AD%8C.mp3", Some("歌.mp3")),
+            ("attachment; filename*=ISO-8859-1''caf%E9.mp3", Some("café.mp3")),
+            // The extended value wins wherever it is
+            ("attachment; filename=\"fallback.mp3\"; filename*=UTF-8''real.mp3", Some("real.mp3")),
+            ("attachment; filename*=UTF-8''real.mp3; filename=fallback.mp3", Some("real.mp3")),
+            // Unusable extended values fall back to the plain one
+            ("attachment; filename*=KOI8-R''%C1.mp3; filename=plain.mp3", Some("plain.mp3")),
+            ("attachment; filename*=UTF-8''%FF.mp3; filename=plain.mp3", Some("plain.mp3")),
+            ("attachment; filename*=no-quotes.mp3; filename=plain.mp3", Some("plain.mp3")),
+            ("attachment; filename=\"\"", None),
+            ("attachment", None),
+            ("", None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(disposition_file_name(header).as_deref(), expected, "{header:?}");
+        }
+    }
+}
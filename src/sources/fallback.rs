@@ -0,0 +1,199 @@
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::Input;
+
+use tracing::info;
+use tracing::warn;
+
+use url::Url;
+
+use crate::config::CONFIG;
+use crate::structs::AudioLink;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+use crate::structs::UnloadedAudioLink;
+
+use super::Source;
+use super::Track;
+use super::catalog::CatalogTrack;
+use super::catalog::similarity;
+use super::meta_cache::METADATA;
+use super::youtube;
+use super::youtube::SearchOptions;
+use super::youtube::YoutubeInfo;
+use super::youtube::search_yt;
+
+/// Lowest similarity of a video accepted as a substitute
+pub const FALLBACK_THRESHOLD: f32 = 0.6;
+
+/// Amount of search results scored
+const FALLBACK_CANDIDATES: usize = 10;
+
+/// Songs played from another Youtube upload because the original one failed to load or play
+pub struct Fallback;
+
+#[async_trait]
+impl Source for Fallback {
+    fn key(&self) -> &'static str {
+        "fallback"
+    }
+
+    fn name(&self) -> &'static str {
+        "Fallback"
+    }
+
+    fn matches(&self, _url: &Url) -> bool {
+        false
+    }
+
+    async fn resolve(&self, _url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        Ok(None)
+    }
+
+    /// Exported substitutes come back as plain videos
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        Ok(AudioLink::new(youtube::load(id).await?))
+    }
+}
+
+/// A video played in place of a song which failed
+#[derive(Debug, Clone)]
+pub struct Substitute {
+    /// The title of the original song
+    pub original: String,
+    pub original_link: Option<String>,
+    pub video: YoutubeInfo,
+    /// Similarity to the original, from 0 to 1
+    pub score: f32,
+}
+
+#[async_trait]
+impl Track for Substitute {
+    fn source(&self) -> &'static dyn Source {
+        &Fallback
+    }
+
+    fn title(&self) -> String {
+        self.video.title.clone()
+    }
+
+    fn duration(&self) -> u32 {
+        self.video.duration
+    }
+
+    fn video(&self) -> Option<&YoutubeInfo> {
+        Some(&self.video)
+    }
+
+    fn note(&self) -> Option<String> {
+        Some(format!(":repeat: substitute for `{}`", self.original))
+    }
+
+    fn input(&self) -> Input {
+        self.video.input()
+    }
+
+    fn unload(&self) -> String {
+        self.video.id.clone()
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let original = match &self.original_link {
+            Some(link) => format!("[{}]({})", self.original, link),
+            None => format!("`{}`", self.original),
+        };
+        CreateEmbed::new()
+            .title(&self.video.title)
+            .url(self.video.url())
+            .field("Channel", &self.video.channel, true)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new("Audio source from Youtube"))
+            .field("Substitute for", original, false)
+            .field("Similarity", format!("{:.0}%", self.score * 100.0), false)
+    }
+}
+
+/// What is known about a song, used to look for another upload of it
+#[derive(Debug, Clone)]
+pub struct Hint {
+    pub title: String,
+    pub channel: Option<String>,
+    /// Duration in seconds, 0 if unknown
+    pub duration: u32,
+    pub link: Option<String>,
+    /// The video which failed, never picked as its own substitute
+    pub video_id: Option<String>,
+}
+
+impl Hint {
+    /// From the metadata cached when the link was last loaded, only Youtube links are cached
+    pub fn from_unloaded(link: &UnloadedAudioLink) -> Option<Self> {
+        match link.source.as_str() {
+            "yt" => METADATA.get_stale::<YoutubeInfo>(&format!("yt:{}", link.id)).map(|info| Self::from_video(&info)),
+            _ => None,
+        }
+    }
+
+    pub fn from_video(info: &YoutubeInfo) -> Self {
+        Hint {
+            title: info.title.clone(),
+            channel: Some(info.channel.clone()),
+            duration: info.duration,
+            link: Some(info.url()),
+            video_id: Some(info.id.clone()),
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    CONFIG.player.fallback
+}
+
+/// Search Youtube for the song, `None` if fallbacks are disabled or no video is similar enough
+pub async fn find_substitute(hint: &Hint) -> Option<Substitute> {
+    if !is_enabled() {
+        return None;
+    }
+    let query = match &hint.channel {
+        Some(channel) => format!("{} {}", channel, hint.title),
+        None => hint.title.clone(),
+    };
+    let results = search_yt(&query, &SearchOptions::default()).await
+        .inspect_err(|e| warn!("Fallback search failed: {e}"))
+        .ok()?;
+    // Scored like the tracks of the music services
+    let track = CatalogTrack {
+        title: hint.title.clone(),
+        artist: hint.channel.clone().unwrap_or_default(),
+        album: None,
+        duration: hint.duration,
+        link: hint.link.clone().unwrap_or_default(),
+    };
+    let (video, score) = results.into_iter()
+        .take(FALLBACK_CANDIDATES)
+        .filter(|video| hint.video_id.as_ref() != Some(&video.id))
+        .map(|video| {
+            let score = similarity(&track, &video);
+            (video, score)
+        })
+        .filter(|(_, score)| *score >= FALLBACK_THRESHOLD)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    info!("`{}` substituted by `{}` ({:.0}%)", hint.title, video.title, score * 100.0);
+    Some(Substitute {
+        original: hint.title.clone(),
+        original_link: hint.link.clone(),
+        video,
+        score,
+    })
+}
+
+/// Replace a failed load with a substitute when one is found
+pub async fn or_substitute(result: Result<ParseResult, ParseError>, hint: Option<Hint>) -> Result<ParseResult, ParseError> {
+    let (Err(e), Some(hint)) = (&result, hint) else { return result };
+    match find_substitute(&hint).await {
+        Some(substitute) => Ok(ParseResult::Single(AudioLink::new(substitute))),
+        None => Err(e.clone()),
+    }
+}
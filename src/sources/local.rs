@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::Input;
+
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use url::Url;
+
+use crate::config::CONFIG;
+use crate::structs::AudioLink;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::probe::probe;
+
+/// File extensions scanned into the library
+const AUDIO_EXTENSIONS: &[&str] = &["flac", "mp3", "ogg", "opus", "wav", "m4a", "aac", "alac", "mka"];
+
+/// The index of the local music library, empty until the first scan
+pub static LIBRARY: LazyLock<Library> = LazyLock::new(Library::new);
+
+/// Audio files in the local music library, configured by `MUSIC_LIBRARY`
+pub struct Local;
+
+#[async_trait]
+impl Source for Local {
+    fn key(&self) -> &'static str {
+        "lib"
+    }
+
+    fn name(&self) -> &'static str {
+        "Local library"
+    }
+
+    fn matches(&self, _url: &Url) -> bool {
+        // Library tracks are picked with `library play` or the `lib:` search prefix
+        false
+    }
+
+    async fn resolve(&self, _url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        Ok(None)
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<AudioLink>, String> {
+        if LIBRARY.root().is_none() {
+            return Err(Error::NotConfigured.to_string());
+        }
+        Ok(LIBRARY.search(query).into_iter().map(AudioLink::new).collect())
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        let root = LIBRARY.root().ok_or(Error::NotConfigured)?.to_owned();
+        let path = PathBuf::from(id);
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Error::InvalidPath(id.to_string()).into());
+        }
+        let track = tokio::task::spawn_blocking(move || read_track(&root, &path)).await??;
+        Ok(AudioLink::new(track))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalTrack {
+    /// Path relative to the library root
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: u32,
+}
+
+#[async_trait]
+impl Track for LocalTrack {
+    fn source(&self) -> &'static dyn Source {
+        &Local
+    }
+
+    fn title(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    fn input(&self) -> Input {
+        let root = LIBRARY.root().expect("Local tracks only exist when the library is configured");
+        songbird::input::File::new(root.join(&self.path)).into()
+    }
+
+    fn unload(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.title)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new("Audio source from local library"));
+        if let Some(artist) = &self.artist {
+            m = m.field("Artist", artist, true);
+        }
+        if let Some(album) = &self.album {
+            m = m.field("Album", album, true);
+        }
+        m.field("File", format!("`{}`", self.path.display()), false)
+    }
+}
+
+pub struct Library {
+    tracks: RwLock<Vec<LocalTrack>>,
+    scanning: AtomicBool,
+}
+
+impl Library {
+    fn new() -> Self {
+        Library {
+            tracks: RwLock::new(vec![]),
+            scanning: AtomicBool::new(false),
+        }
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        CONFIG.library.path.as_deref()
+    }
+
+    /// Amount of tracks in the index
+    pub fn len(&self) -> usize {
+        self.tracks.read().expect("Library lock poisoned").len()
+    }
+
+    /// Rebuild the index from the files in the library, returns the amount of tracks found
+    #[instrument(skip(self))]
+    pub async fn rescan(&self) -> Result<usize, Error> {
+        let root = self.root().ok_or(Error::NotConfigured)?.to_owned();
+        if self.scanning.swap(true, Ordering::AcqRel) {
+            return Err(Error::Scanning);
+        }
+        let result = tokio::task::spawn_blocking(move || scan(&root)).await;
+        self.scanning.store(false, Ordering::Release);
+        let mut tracks = result.map_err(|err| Error::Io(std::io::Error::other(err)))??;
+        tracks.sort_by(|a, b| a.path.cmp(&b.path));
+        let count = tracks.len();
+        *self.tracks.write().expect("Library lock poisoned") = tracks;
+        info!("Library scan finished, {count} tracks found");
+        Ok(count)
+    }
+
+    /// Find tracks containing every word of `query` in the title, artist, album or file name,
+    /// matches on the title are ranked first
+    pub fn search(&self, query: &str) -> Vec<LocalTrack> {
+        let words = query.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>();
+        let tracks = self.tracks.read().expect("Library lock poisoned");
+        let mut result = tracks.iter()
+            .filter_map(|track| {
+                let title = track.title.to_lowercase();
+                let other = format!(
+                    "{} {} {}",
+                    track.artist.as_deref().unwrap_or_default(),
+                    track.album.as_deref().unwrap_or_default(),
+                    track.path.to_string_lossy(),
+                ).to_lowercase();
+                let mut score = 0;
+                for word in &words {
+                    if title.contains(word.as_str()) {
+                        score += 2;
+                    } else if other.contains(word.as_str()) {
+                        score += 1;
+                    } else {
+                        return None;
+                    }
+                }
+                Some((score, track))
+            })
+            .collect::<Vec<_>>();
+        result.sort_by(|(a, _), (b, _)| b.cmp(a));
+        result.into_iter().map(|(_, track)| track.clone()).collect()
+    }
+}
+
+/// Walk the library, symlinked directories are followed once so a link loop can't keep the scan going,
+/// the directories which can't be read are skipped
+fn scan(root: &Path) -> Result<Vec<LocalTrack>, Error> {
+    let mut tracks = vec![];
+    let mut visited = HashSet::from([root.canonicalize()?]);
+    let mut dirs = vec![root.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if dir == root => return Err(err.into()),
+            Err(err) => {
+                warn!("Skipped {}: {err}", dir.display());
+                continue;
+            },
+        };
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    warn!("Skipped an entry of {}: {err}", dir.display());
+                    continue;
+                },
+            };
+            if path.is_dir() {
+                match path.canonicalize() {
+                    Ok(real) => if visited.insert(real) {
+                        dirs.push(path);
+                    } else {
+                        warn!("Skipped {}, the directory was already scanned", path.display());
+                    },
+                    Err(err) => warn!("Skipped {}: {err}", path.display()),
+                }
+                continue;
+            }
+            let is_audio = path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_audio {
+                continue;
+            }
+            let relative = path.strip_prefix(root).expect("Scanned path is in the root").to_owned();
+            match read_track(root, &relative) {
+                Ok(track) => tracks.push(track),
+                Err(err) => warn!("Skipped {}: {err}", path.display()),
+            }
+        }
+    }
+    Ok(tracks)
+}
+
+/// Read the tags of a file in the library, the title falls back to the file name
+fn read_track(root: &Path, path: &Path) -> Result<LocalTrack, Error> {
+    let file = File::open(root.join(path))?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let tags = probe(Box::new(file), extension).ok_or_else(|| Error::Unreadable(path.to_owned()))?;
+    let title = tags.title.unwrap_or_else(|| {
+        path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+    });
+    Ok(LocalTrack {
+        path: path.to_owned(),
+        title,
+        artist: tags.artist,
+        album: tags.album,
+        duration: tags.duration.unwrap_or_default(),
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The music library is not configured")]
+    NotConfigured,
+    #[error("The music library is already being scanned")]
+    Scanning,
+    #[error("Invalid library path: {0}")]
+    InvalidPath(String),
+    #[error("Unreadable audio file: {}", .0.display())]
+    Unreadable(PathBuf),
+    #[error("std::io error: {0}")]
+    Io(#[from] std::io::Error),
+}
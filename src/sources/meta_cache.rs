@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+use crate::config::CONFIG;
+
+/// Metadata fetched with yt-dlp, kept on disk so restarts and imports don't fetch it again
+pub static METADATA: LazyLock<MetadataCache> = LazyLock::new(MetadataCache::new);
+
+/// How often the changes are written to the disk
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When over the size limit, the oldest entries are dropped until this ratio of the limit is left
+const EVICT_RATIO: f64 = 0.9;
+
+/// Lookups cached by a key like `yt:<id>`, the values are stored as JSON
+pub struct MetadataCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Total size of the values in bytes, only changed while holding the lock of `entries`
+    size: AtomicU64,
+    /// Set when the entries changed since the last save
+    dirty: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Unix time when the value was stored
+    stored: u64,
+    value: String,
+}
+
+pub struct Stats {
+    pub entries: usize,
+    /// Total size of the values in bytes
+    pub size: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Age of the oldest entry
+    pub oldest: Option<Duration>,
+}
+
+impl MetadataCache {
+    fn new() -> Self {
+        MetadataCache {
+            entries: Mutex::new(HashMap::new()),
+            size: AtomicU64::new(0),
+            dirty: AtomicBool::new(false),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        CONFIG.cache.metadata_max_size > 0
+    }
+
+    fn file(&self) -> PathBuf {
+        CONFIG.cache.dir.join("metadata.cbor")
+    }
+
+    /// Get a value which hasn't expired
+    ///
+    /// Expired entries are kept until replaced or evicted, `get_stale()` still reads them
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        let value = match entries.get(key) {
+            Some(entry) if now().saturating_sub(entry.stored) < CONFIG.cache.metadata_ttl.as_secs() => {
+                serde_json::from_str(&entry.value).ok()
+            },
+            _ => None,
+        };
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Get a value even if it has expired, e.g. the last known title of a removed video
+    pub fn get_stale<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        serde_json::from_str(&entries.get(key)?.value).ok()
+    }
+
+    pub fn insert<T: Serialize>(&self, key: String, value: &T) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Ok(value) = serde_json::to_string(value) else { return };
+        let mut entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        let added = value.len() as u64;
+        let replaced = entries.insert(key, Entry { stored: now(), value })
+            .map_or(0, |old| old.value.len() as u64);
+        let mut size = self.size.load(Ordering::Relaxed) + added - replaced;
+        self.dirty.store(true, Ordering::Release);
+
+        if size > CONFIG.cache.metadata_max_size {
+            let target = (CONFIG.cache.metadata_max_size as f64 * EVICT_RATIO) as u64;
+            let mut by_age = entries.iter()
+                .map(|(key, entry)| (entry.stored, entry.value.len() as u64, key.clone()))
+                .collect::<Vec<_>>();
+            by_age.sort();
+            for (_, len, key) in by_age {
+                if size <= target {
+                    break;
+                }
+                entries.remove(&key);
+                size -= len;
+            }
+        }
+        self.size.store(size, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> Stats {
+        let entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        Stats {
+            entries: entries.len(),
+            size: self.size.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            oldest: entries.values()
+                .map(|entry| entry.stored)
+                .min()
+                .map(|stored| Duration::from_secs(now().saturating_sub(stored))),
+        }
+    }
+
+    /// Keys containing `filter` and the age of their entries, newest first
+    pub fn keys(&self, filter: &str) -> Vec<(String, Duration)> {
+        let entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        let mut keys = entries.iter()
+            .filter(|(key, _)| key.contains(filter))
+            .map(|(key, entry)| (key.clone(), Duration::from_secs(now().saturating_sub(entry.stored))))
+            .collect::<Vec<_>>();
+        keys.sort_by_key(|(_, age)| *age);
+        keys
+    }
+
+    /// Remove the entries whose key contains `filter`, returns the amount removed
+    pub fn flush(&self, filter: &str) -> usize {
+        let mut entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        let count = entries.len();
+        let mut removed = 0;
+        entries.retain(|key, entry| {
+            let keep = !key.contains(filter);
+            if !keep {
+                removed += entry.value.len() as u64;
+            }
+            keep
+        });
+        self.size.fetch_sub(removed, Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Release);
+        count - entries.len()
+    }
+
+    /// Read the entries saved by the last run
+    #[instrument(skip(self))]
+    pub async fn load(&self) -> Result<usize, Error> {
+        let file = self.file();
+        let loaded = tokio::task::spawn_blocking(move || -> Result<_, Error> {
+            match std::fs::read(&file) {
+                Ok(data) => Ok(serde_cbor::from_slice::<HashMap<String, Entry>>(&data)?),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+                Err(err) => Err(err.into()),
+            }
+        }).await??;
+        let mut entries = self.entries.lock().expect("Metadata cache lock poisoned");
+        // Keep what was cached while loading
+        for (key, entry) in loaded {
+            entries.entry(key).or_insert(entry);
+        }
+        self.size.store(entries.values().map(|entry| entry.value.len() as u64).sum(), Ordering::Relaxed);
+        info!("Metadata cache loaded, {} entries", entries.len());
+        Ok(entries.len())
+    }
+
+    /// Write the entries to the disk if they changed
+    #[instrument(skip(self))]
+    pub async fn save(&self) -> Result<(), Error> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let data = {
+            let entries = self.entries.lock().expect("Metadata cache lock poisoned");
+            serde_cbor::to_vec(&*entries)?
+        };
+        let file = self.file();
+        let result = tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            if let Some(dir) = file.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            // Write to a temporary file first, a crash can't leave a broken cache behind
+            let temp = file.with_extension("cbor.tmp");
+            std::fs::write(&temp, data)?;
+            std::fs::rename(&temp, &file)?;
+            Ok(())
+        }).await?;
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    /// Load the saved entries, then save the changes periodically
+    pub async fn run(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Err(e) = self.load().await {
+            warn!("Failed to load the metadata cache: {e}");
+        }
+        loop {
+            tokio::time::sleep(SAVE_INTERVAL).await;
+            if let Err(e) = self.save().await {
+                error!("Failed to save the metadata cache: {e}");
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("std::io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("CBOR error: {0}")]
+    Cbor(#[from] serde_cbor::Error),
+    #[error("Task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
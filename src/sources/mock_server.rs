@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+
+/// A local HTTP server for testing the API clients,
+/// answers each request with the response of its route, `404` for unknown routes
+pub struct MockServer {
+    /// The base URL, e.g. `http://127.0.0.1:1234`
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Serve `(target, status, body)` routes, the target is the path with the query if any,
+    /// `{url}` in the bodies is replaced by the base URL
+    pub async fn start(routes: &[(&str, u16, &str)]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind the mock server");
+        let url = format!("http://{}", listener.local_addr().expect("Bound socket has an address"));
+        let routes = routes.iter()
+            .map(|(target, status, body)| (target.to_string(), *status, body.replace("{url}", &url)))
+            .collect::<Vec<_>>();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let log = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut data = vec![];
+                let mut buf = [0; 4096];
+                while !data.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => data.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&data);
+                let target = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let (status, body) = routes.iter()
+                    .find(|(route, _, _)| *route == target)
+                    .map_or((404, "{}"), |(_, status, body)| (*status, body.as_str()));
+                log.lock().unwrap().push(target);
+                let response = format!(
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len(),
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        MockServer { url, requests }
+    }
+
+    /// The targets requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
@@ -1 +1,208 @@
+use std::any::Any;
+use std::fmt::Debug;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+
+use songbird::input::Input;
+
+use url::Url;
+
+use crate::structs::AudioLink;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use fallback::Hint;
+use youtube::YoutubeInfo;
+
+pub mod attachment;
+pub mod audio_cache;
+pub mod catalog;
+pub mod direct;
+pub mod fallback;
+pub mod local;
+pub mod meta_cache;
+#[cfg(test)]
+mod mock_server;
+pub mod playlist;
+pub mod podcast;
+pub mod probe;
+pub mod radio;
+pub mod runner;
 pub mod youtube;
+pub mod youtube_api;
+pub mod ytdlp;
+
+/// All the registered sources, `AudioLink::parse()` tries the ones matching the link in order
+pub static SOURCES: &[&dyn Source] = &[
+    &youtube::Youtube,
+    &catalog::Catalog,
+    &local::Local,
+    &attachment::Discord,
+    &ytdlp::SOUNDCLOUD,
+    // Accept any http link, keep them after the sources matching specific hosts
+    &radio::Radio,
+    &direct::Direct,
+    &ytdlp::GENERIC,
+    // Matches no link, only restores the exported substitutes
+    &fallback::Fallback,
+];
+
+/// A backend providing playable audio
+///
+/// A backend is self-contained in its module: it implements this trait, and `Track` for the metadata
+/// of its songs, then the source is registered in `SOURCES`
+#[async_trait]
+pub trait Source: Sync {
+    /// Short name, used as the search prefix (e.g. `yt:`) and the tag of `UnloadedAudioLink`
+    fn key(&self) -> &'static str;
+
+    /// Name shown to the users
+    fn name(&self) -> &'static str;
+
+    /// Check if the link should be handled by this source
+    fn matches(&self, url: &Url) -> bool;
+
+    /// Fetch the metadata of the link
+    ///
+    /// Returns `Ok(None)` if the link turns out not to belong to this source,
+    /// `AudioLink::parse()` then tries the next matching source
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError>;
+
+    /// Search for `query`, the results are ordered by relevance
+    async fn search(&self, _query: &str) -> Result<Vec<AudioLink>, String> {
+        Err(format!("Searching on {} is not supported", self.name()))
+    }
+
+    /// Restore the link from the identifier created by `Track::unload()`
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink>;
+}
+
+/// The metadata of a song, each source has its own type wrapped in `AudioLink`
+#[async_trait]
+pub trait Track: Any + Debug + Send + Sync {
+    /// The source providing the song
+    fn source(&self) -> &'static dyn Source;
+
+    /// Name shown in the lists and the replies, e.g. `Artist - Title`
+    fn title(&self) -> String;
+
+    /// Duration in seconds, 0 if unknown
+    fn duration(&self) -> u32;
+
+    /// Live streams never end, their duration is unknown
+    fn is_live(&self) -> bool {
+        false
+    }
+
+    /// The duration as shown to the users
+    fn time_str(&self) -> String {
+        if self.is_live() {
+            return "LIVE".to_string();
+        }
+        let t = self.duration();
+        format!("{}:{:02}", t / 60, t % 60)
+    }
+
+    /// The Youtube video actually played, its audio may be in the audio cache
+    fn video(&self) -> Option<&YoutubeInfo> {
+        None
+    }
+
+    /// Identifies the song played, the songs playing the same Youtube video share it
+    fn identity(&self) -> String {
+        match self.video() {
+            Some(video) => format!("yt:{}", video.id),
+            None => format!("{}:{}", self.source().key(), self.unload()),
+        }
+    }
+
+    /// A short note shown after the title in the queue, e.g. a doubtful match
+    fn note(&self) -> Option<String> {
+        None
+    }
+
+    /// What is known to look for another upload of the song, `None` if it can't be replaced
+    fn hint(&self) -> Option<Hint> {
+        None
+    }
+
+    /// Create the songbird input
+    fn input(&self) -> Input;
+
+    /// Serialize into an identifier accepted by `Source::load()`
+    fn unload(&self) -> String;
+
+    /// The details shown by `now_playing`, `position` is the time played in seconds
+    async fn embed(&self, position: Option<u32>) -> CreateEmbed;
+}
+
+/// Query parameters only used to track where a link was shared, `utm_*` ones are removed as well
+const TRACKING_PARAMS: &[&str] = &["si", "fbclid", "gclid", "igshid", "ref_src", "mc_cid", "mc_eid"];
+
+/// Rewrite the link in a single form, so the same song isn't loaded and cached under several links
+///
+/// Youtube links are rebuilt from the video and playlist IDs, the tracking parameters are removed from the others
+pub fn canonicalize(url: Url) -> Url {
+    if let Some(link) = youtube::YoutubeLink::parse(&url) {
+        return Url::parse(&link.url()).unwrap_or(url);
+    }
+    let is_tracking = |key: &str| key.starts_with("utm_") || TRACKING_PARAMS.contains(&key);
+    if !url.query_pairs().any(|(key, _)| is_tracking(&key)) {
+        return url;
+    }
+    let pairs = url.query_pairs()
+        .filter(|(key, _)| !is_tracking(key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    let mut url = url;
+    url.set_query(None);
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+    url
+}
+
+/// Find a source by its key
+pub fn find_source(key: &str) -> Option<&'static dyn Source> {
+    SOURCES.iter()
+        .find(|source| source.key().eq_ignore_ascii_case(key))
+        .copied()
+}
+
+/// Split the source prefix from a search query, uses Youtube when there's no known prefix
+pub fn split_query(query: &str) -> (&'static dyn Source, &str) {
+    if let Some((prefix, rest)) = query.split_once(':') {
+        if let Some(source) = find_source(prefix.trim()) {
+            return (source, rest.trim());
+        }
+    }
+    (&youtube::Youtube, query.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_links() {
+        let cases = [
+            ("https://youtu.be/dQw4w9WgXcQ?si=abc", "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&si=abc&t=42", "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ("https://music.youtube.com/watch?v=dQw4w9WgXcQ&feature=share", "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ("https://www.youtube.com/shorts/dQw4w9WgXcQ", "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ?feature=shared", "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ("https://www.youtube.com/embed/dQw4w9WgXcQ", "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ("https://www.youtube.com/watch?list=PL123&v=dQw4w9WgXcQ", "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123"),
+            // Links of other sites only lose the tracking parameters
+            ("https://soundcloud.com/a/b?si=abc&utm_source=x&utm_medium=y", "https://soundcloud.com/a/b"),
+            ("https://example.com/a.mp3?token=1&fbclid=2&b=3", "https://example.com/a.mp3?token=1&b=3"),
+            ("https://example.com/a.mp3?token=1", "https://example.com/a.mp3?token=1"),
+            ("https://www.youtube.com/@channel?si=abc", "https://www.youtube.com/@channel"),
+        ];
+        for (link, expected) in cases {
+            let url = Url::parse(link).expect("valid URL");
+            assert_eq!(canonicalize(url).as_str(), expected, "{link}");
+        }
+    }
+}
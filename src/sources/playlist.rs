@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use crate::structs::AudioLink;
+
+/// An entry of a playlist file
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    /// The link or path of the entry, as written in the file
+    pub location: String,
+    pub title: Option<String>,
+    /// Duration in seconds, `None` for streams or when unknown
+    pub duration: Option<u32>,
+}
+
+/// Formats of playlist files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    /// `.m3u`, optionally with `#EXTINF` lines
+    M3u,
+    /// `.pls`, the format used by SHOUTcast
+    Pls,
+    /// `.xspf`, the XML format of VLC and others
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// Guess the format from the extension of a file name or URL path
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from a `Content-Type` without parameters
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "audio/x-mpegurl" | "audio/mpegurl" => Some(Self::M3u),
+            "audio/x-scpls" | "audio/scpls" => Some(Self::Pls),
+            "application/xspf+xml" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the content, a plain list of links is taken as M3U
+    pub fn detect(text: &str) -> Option<Self> {
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        let first_line = text.lines().next().unwrap_or_default().trim();
+        if first_line.starts_with("#EXTM3U") || AudioLink::is_link(first_line) {
+            Some(Self::M3u)
+        } else if first_line.eq_ignore_ascii_case("[playlist]") {
+            Some(Self::Pls)
+        } else if text.starts_with("<?xml") || text.starts_with("<playlist") {
+            Some(Self::Xspf)
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Vec<PlaylistEntry> {
+        let text = text.trim_start_matches('\u{feff}');
+        match self {
+            Self::M3u => parse_m3u(text),
+            Self::Pls => parse_pls(text),
+            Self::Xspf => parse_xspf(text),
+        }
+    }
+}
+
+fn parse_m3u(text: &str) -> Vec<PlaylistEntry> {
+    let mut entries = vec![];
+    let mut info = None;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<duration>[ <attributes>],<title>
+            let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let duration = head.split_whitespace().next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|d| *d > 0.0)
+                .map(|d| d as u32);
+            let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            info = Some((title, duration));
+        } else if !line.starts_with('#') {
+            let (title, duration) = info.take().unwrap_or_default();
+            entries.push(PlaylistEntry { location: line.to_string(), title, duration });
+        }
+    }
+    entries
+}
+
+fn parse_pls(text: &str) -> Vec<PlaylistEntry> {
+    let mut entries = BTreeMap::<u32, PlaylistEntry>::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let (field, index) = key.split_at(key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len()));
+        let Ok(index) = index.parse::<u32>() else { continue };
+        let entry = entries.entry(index).or_insert_with(|| PlaylistEntry {
+            location: String::new(),
+            title: None,
+            duration: None,
+        });
+        match field {
+            "file" => entry.location = value.to_string(),
+            "title" if !value.is_empty() => entry.title = Some(value.to_string()),
+            // -1 means a stream or unknown
+            "length" => entry.duration = value.parse::<i64>().ok().filter(|d| *d > 0).map(|d| d as u32),
+            _ => {},
+        }
+    }
+    entries.into_values()
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+/// Read the `<track>` elements, stops at the first XML error
+fn parse_xspf(text: &str) -> Vec<PlaylistEntry> {
+    let mut reader = Reader::from_str(text);
+    let mut entries = vec![];
+    let mut track = None::<PlaylistEntry>;
+    let mut element = Vec::new();
+    loop {
+        let content = match reader.read_event() {
+            Ok(Event::Start(tag)) => {
+                element = tag.local_name().as_ref().to_vec();
+                if element == b"track" {
+                    track = Some(PlaylistEntry { location: String::new(), title: None, duration: None });
+                }
+                continue;
+            },
+            Ok(Event::End(tag)) => {
+                if tag.local_name().as_ref() == b"track" {
+                    entries.extend(track.take().filter(|entry| !entry.location.is_empty()));
+                }
+                element.clear();
+                continue;
+            },
+            Ok(Event::Text(text)) => text.unescape().map(|text| text.into_owned()).unwrap_or_default(),
+            Ok(Event::CData(data)) => String::from_utf8_lossy(&data).into_owned(),
+            Ok(Event::Eof) | Err(_) => break,
+            _ => continue,
+        };
+        let (Some(entry), content) = (track.as_mut(), content.trim()) else { continue };
+        match element.as_slice() {
+            // A track may list several locations, the first one is preferred
+            b"location" if entry.location.is_empty() => entry.location = content.to_string(),
+            b"title" if !content.is_empty() => entry.title = Some(content.to_string()),
+            // In milliseconds
+            b"duration" => entry.duration = content.parse::<u64>().ok().map(|ms| (ms / 1000) as u32),
+            _ => {},
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The location, title and duration of each entry
+    fn summary(entries: Vec<PlaylistEntry>) -> Vec<(String, Option<String>, Option<u32>)> {
+        entries.into_iter()
+            .map(|entry| (entry.location, entry.title, entry.duration))
+            .collect()
+    }
+
+    fn entry(location: &str, title: Option<&str>, duration: Option<u32>) -> (String, Option<String>, Option<u32>) {
+        (location.to_string(), title.map(str::to_string), duration)
+    }
+
+    #[test]
+    fn m3u() {
+        let cases = [
+            ("http://a.test/1.mp3\nhttp://a.test/2.mp3", vec![
+                entry("http://a.test/1.mp3", None, None),
+                entry("http://a.test/2.mp3", None, None),
+            ]),
+            ("\u{feff}#EXTM3U\n#EXTINF:123,Artist - Song\nhttp://a.test/1.mp3\n", vec![
+                entry("http://a.test/1.mp3", Some("Artist - Song"), Some(123)),
+            ]),
+            ("#EXTM3U\n#EXTINF:-1 tvg-id=\"x\",Radio\nhttp://a.test/live\n", vec![
+                entry("http://a.test/live", Some("Radio"), None),
+            ]),
+            // Relative entries are kept as written, the info of the previous entry isn't reused
+            ("#EXTINF:60,First\nmusic/first.mp3\n\n# comment\n../second.ogg\r\n", vec![
+                entry("music/first.mp3", Some("First"), Some(60)),
+                entry("../second.ogg", None, None),
+            ]),
+            ("#EXTM3U\n", vec![]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(summary(PlaylistFormat::M3u.parse(text)), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn pls() {
+        let cases = [
+            ("[playlist]\nFile1=http://a.test/1\nTitle1=One\nLength1=-1\nNumberOfEntries=1\nVersion=2", vec![
+                entry("http://a.test/1", Some("One"), None),
+            ]),
+            // Ordered by index, gaps and entries without a file are skipped
+            ("[playlist]\nfile3=http://a.test/3\nLength3=90\nTitle2=No file\nFile1=http://a.test/1\n", vec![
+                entry("http://a.test/1", None, None),
+                entry("http://a.test/3", None, Some(90)),
+            ]),
+            ("[playlist]\nNumberOfEntries=0\n", vec![]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(summary(PlaylistFormat::Pls.parse(text)), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn xspf() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>List</title>
+  <trackList>
+    <track>
+      <location>http://a.test/1.mp3</location>
+      <location>http://b.test/1.mp3</location>
+      <title>One &amp; Two</title>
+      <duration>61500</duration>
+    </track>
+    <track><title>No location</title></track>
+    <track><location><![CDATA[http://a.test/2.mp3?a=1&b=2]]></location></track>
+  </trackList>
+</playlist>"#;
+        assert_eq!(summary(PlaylistFormat::Xspf.parse(text)), vec![
+            entry("http://a.test/1.mp3", Some("One & Two"), Some(61)),
+            entry("http://a.test/2.mp3?a=1&b=2", None, None),
+        ]);
+    }
+
+    #[test]
+    fn detect() {
+        let cases = [
+            ("#EXTM3U\nhttp://a.test/1", Some(PlaylistFormat::M3u)),
+            ("\u{feff}  https://a.test/1.mp3", Some(PlaylistFormat::M3u)),
+            ("[Playlist]\nFile1=http://a.test/1", Some(PlaylistFormat::Pls)),
+            ("<?xml version=\"1.0\"?><playlist/>", Some(PlaylistFormat::Xspf)),
+            ("<playlist version=\"1\"/>", Some(PlaylistFormat::Xspf)),
+            ("just some words", None),
+            ("", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(PlaylistFormat::detect(text), expected, "{text:?}");
+        }
+    }
+}
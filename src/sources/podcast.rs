@@ -0,0 +1,382 @@
+use quick_xml::Reader;
+use quick_xml::events::BytesStart;
+use quick_xml::events::Event;
+
+use serde_json::Value;
+
+use tokio::sync::OnceCell;
+
+use tracing::instrument;
+
+use url::Url;
+
+use crate::CLIENT;
+use crate::config::CONFIG;
+
+use super::direct::Chapter;
+use super::direct::DirectInfo;
+
+/// Largest feed downloaded, in bytes
+const MAX_FEED_SIZE: usize = 16 * 1024 * 1024;
+
+/// A podcast feed, the episodes are played as direct media links
+#[derive(Debug)]
+pub struct Feed {
+    pub title: String,
+    /// Episodes with an audio enclosure, newest first as listed in the feed
+    pub episodes: Vec<DirectInfo>,
+}
+
+/// Download and parse an RSS or Atom feed
+#[instrument]
+pub async fn fetch_feed(url: &str) -> Result<Feed, Error> {
+    let url = Url::parse(url)?;
+    let mut response = CLIENT.get(url.clone())
+        .timeout(CONFIG.http.timeout)
+        .send().await?
+        .error_for_status()?;
+    let mut data = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk);
+        if data.len() > MAX_FEED_SIZE {
+            return Err(Error::TooLarge);
+        }
+    }
+    let feed = parse_feed(&String::from_utf8_lossy(&data), &url)?;
+    if feed.episodes.is_empty() {
+        return Err(Error::NoEpisode);
+    }
+    Ok(feed)
+}
+
+/// Fetch the chapters of an episode from a JSON chapters file (Podcasting 2.0)
+#[instrument]
+pub async fn fetch_chapters(url: &str) -> Result<Vec<Chapter>, Error> {
+    let data = CLIENT.get(url)
+        .timeout(CONFIG.http.timeout)
+        .send().await?
+        .error_for_status()?
+        .bytes().await?;
+    let value = serde_json::from_slice::<Value>(&data)?;
+    let chapters = value["chapters"].as_array()
+        .into_iter()
+        .flatten()
+        // Chapters with `toc: false` are not meant to be listed
+        .filter(|chapter| chapter["toc"].as_bool() != Some(false))
+        .filter_map(|chapter| Some(Chapter {
+            start: chapter["startTime"].as_f64()? as u32,
+            title: chapter["title"].as_str().unwrap_or_default().to_string(),
+        }))
+        .collect();
+    Ok(chapters)
+}
+
+fn parse_feed(text: &str, url: &Url) -> Result<Feed, Error> {
+    let mut reader = Reader::from_str(text);
+    let mut title = None;
+    let mut episodes = vec![];
+    let mut episode = None::<Episode>;
+    let mut element = Vec::new();
+    loop {
+        let content = match reader.read_event()? {
+            Event::Start(tag) => {
+                element = tag.local_name().as_ref().to_vec();
+                match element.as_slice() {
+                    b"item" | b"entry" => episode = Some(Episode::default()),
+                    _ => if let Some(episode) = &mut episode {
+                        episode.read_attributes(&tag);
+                    },
+                }
+                continue;
+            },
+            Event::Empty(tag) => {
+                if let Some(episode) = &mut episode {
+                    episode.read_attributes(&tag);
+                }
+                continue;
+            },
+            Event::End(tag) => {
+                if matches!(tag.local_name().as_ref(), b"item" | b"entry") {
+                    let feed_title = title.clone().unwrap_or_default();
+                    episodes.extend(episode.take().and_then(|episode| episode.into_info(url, feed_title)));
+                }
+                element.clear();
+                continue;
+            },
+            Event::Text(text) => text.unescape()?.into_owned(),
+            Event::CData(data) => String::from_utf8_lossy(&data).into_owned(),
+            Event::Eof => break,
+            _ => continue,
+        };
+        let content = content.trim();
+        if content.is_empty() {
+            continue;
+        }
+        match &mut episode {
+            Some(episode) => match element.as_slice() {
+                b"title" => episode.title = Some(content.to_string()),
+                b"duration" => episode.duration = parse_timestamp(content),
+                b"pubDate" | b"published" => episode.published = Some(short_date(content)),
+                b"updated" if episode.published.is_none() => episode.published = Some(short_date(content)),
+                _ => {},
+            },
+            // The first title outside the episodes is the one of the feed
+            None if element == b"title" && title.is_none() => title = Some(content.to_string()),
+            None => {},
+        }
+    }
+    Ok(Feed {
+        title: title.unwrap_or_else(|| url.to_string()),
+        episodes,
+    })
+}
+
+/// An `<item>` (RSS) or `<entry>` (Atom) being parsed
+#[derive(Default)]
+struct Episode {
+    title: Option<String>,
+    enclosure: Option<String>,
+    content_type: Option<String>,
+    duration: Option<u32>,
+    published: Option<String>,
+    chapters: Vec<Chapter>,
+    chapters_url: Option<String>,
+}
+
+impl Episode {
+    /// Read the elements carrying their data in attributes
+    fn read_attributes(&mut self, tag: &BytesStart) {
+        let attribute = |key: &[u8]| tag.attributes()
+            .flatten()
+            .find(|attr| attr.key.local_name().as_ref() == key)
+            .and_then(|attr| attr.unescape_value().ok())
+            .map(|value| value.trim().to_string());
+        match tag.local_name().as_ref() {
+            // <enclosure url="" type="" length=""/>
+            b"enclosure" if self.enclosure.is_none() => {
+                self.enclosure = attribute(b"url");
+                self.content_type = attribute(b"type");
+            },
+            // <link rel="enclosure" href="" type=""/>
+            b"link" if self.enclosure.is_none() && attribute(b"rel").as_deref() == Some("enclosure") => {
+                self.enclosure = attribute(b"href");
+                self.content_type = attribute(b"type");
+            },
+            // <podcast:chapters url="" type="application/json+chapters"/>
+            b"chapters" => {
+                if let Some(url) = attribute(b"url") {
+                    self.chapters_url = Some(url);
+                }
+            },
+            // <psc:chapter start="00:01:02.000" title=""/>
+            b"chapter" => {
+                if let Some(start) = attribute(b"start").as_deref().and_then(parse_timestamp) {
+                    let title = attribute(b"title").unwrap_or_default();
+                    self.chapters.push(Chapter { start, title });
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Episodes without an audio enclosure are skipped
+    fn into_info(self, feed_url: &Url, feed_title: String) -> Option<DirectInfo> {
+        let is_audio = self.content_type.as_deref()
+            .is_none_or(|t| t.starts_with("audio/") || t.starts_with("application/ogg"));
+        if !is_audio {
+            return None;
+        }
+        // Relative links are allowed in Atom feeds
+        let url = feed_url.join(&self.enclosure?).ok()?.to_string();
+        Some(DirectInfo {
+            title: self.title.unwrap_or_else(|| url.clone()),
+            url,
+            artist: Some(feed_title),
+            album: None,
+            duration: self.duration.unwrap_or_default(),
+            content_type: self.content_type,
+            published: self.published,
+            // Chapters listed in the feed are used over the chapters file
+            chapters: match self.chapters.is_empty() {
+                true => OnceCell::new(),
+                false => OnceCell::new_with(Some(self.chapters)),
+            },
+            chapters_url: self.chapters_url,
+        })
+    }
+}
+
+/// Parse `3723`, `62:03` or `01:02:03.500` into seconds
+fn parse_timestamp(text: &str) -> Option<u32> {
+    text.split(':')
+        .try_fold(0.0, |acc, part| Some(acc * 60.0 + part.trim().parse::<f64>().ok()?))
+        .map(|seconds| seconds as u32)
+}
+
+/// Drop the time from RFC 2822 (RSS) and RFC 3339 (Atom) dates,
+/// e.g. `Tue, 10 Jun 2003 04:00:00 GMT` becomes `10 Jun 2003`
+fn short_date(text: &str) -> String {
+    if let Some(date) = text.get(..10).filter(|date| date.as_bytes()[4] == b'-') {
+        return date.to_string();
+    }
+    let words = text.split_whitespace()
+        .filter(|word| !word.ends_with(','))
+        .take(3)
+        .collect::<Vec<_>>();
+    words.join(" ")
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("URL parse error: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("The feed is too large")]
+    TooLarge,
+    #[error("Invalid chapters file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid feed: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("No audio episode found in the feed")]
+    NoEpisode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd"
+    xmlns:podcast="https://podcastindex.org/namespace/1.0" xmlns:psc="http://podlove.org/simple-chapters">
+  <channel>
+    <title>Night Show</title>
+    <image><title>Cover</title><url>https://example.com/cover.jpg</url></image>
+    <item>
+      <title><![CDATA[Episode 2 & more]]></title>
+      <pubDate>Tue, 10 Jun 2003 04:00:00 GMT</pubDate>
+      <itunes:duration>01:02:03</itunes:duration>
+      <enclosure url="https://example.com/ep2.mp3" type="audio/mpeg" length="1000"/>
+      <psc:chapters version="1.2">
+        <psc:chapter start="00:00:00.000" title="Intro"/>
+        <psc:chapter start="00:05:30.500" title="News &amp; Notes"/>
+        <psc:chapter start="bogus" title="Skipped"/>
+      </psc:chapters>
+    </item>
+    <item>
+      <title>Episode 1</title>
+      <itunes:duration>3723</itunes:duration>
+      <enclosure url="https://example.com/ep1.mp3"/>
+      <podcast:chapters url="https://example.com/ep1.json" type="application/json+chapters"/>
+    </item>
+    <item>
+      <title>Video episode</title>
+      <enclosure url="https://example.com/ep0.mp4" type="video/mp4"/>
+    </item>
+    <item>
+      <title>No enclosure</title>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Atom Cast</title>
+  <entry>
+    <title>First entry</title>
+    <updated>2024-03-05T10:00:00Z</updated>
+    <link rel="alternate" href="/posts/1"/>
+    <link rel="enclosure" href="media/1.ogg" type="audio/ogg"/>
+  </entry>
+  <entry>
+    <title>Second entry</title>
+    <published>2024-02-01T08:30:00+01:00</published>
+    <updated>2024-03-01T00:00:00Z</updated>
+    <link rel="enclosure" href="https://cdn.example.org/2.mp3"/>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn rss() {
+        let url = Url::parse("https://example.com/feed.xml").unwrap();
+        let feed = parse_feed(RSS, &url).unwrap();
+        assert_eq!(feed.title, "Night Show");
+        assert_eq!(feed.episodes.len(), 2);
+
+        let episode = &feed.episodes[0];
+        assert_eq!(episode.title, "Episode 2 & more");
+        assert_eq!(episode.url, "https://example.com/ep2.mp3");
+        assert_eq!(episode.artist.as_deref(), Some("Night Show"));
+        assert_eq!(episode.duration, 3723);
+        assert_eq!(episode.content_type.as_deref(), Some("audio/mpeg"));
+        assert_eq!(episode.published.as_deref(), Some("10 Jun 2003"));
+        let chapters = episode.chapters.get().expect("Chapters listed in the feed");
+        let chapters = chapters.iter().map(|c| (c.start, c.title.as_str())).collect::<Vec<_>>();
+        assert_eq!(chapters, [(0, "Intro"), (330, "News & Notes")]);
+        assert_eq!(episode.chapters_url, None);
+
+        let episode = &feed.episodes[1];
+        assert_eq!(episode.title, "Episode 1");
+        assert_eq!(episode.duration, 3723);
+        assert_eq!(episode.published, None);
+        assert!(episode.chapters.get().is_none());
+        assert_eq!(episode.chapters_url.as_deref(), Some("https://example.com/ep1.json"));
+    }
+
+    #[test]
+    fn atom() {
+        let url = Url::parse("https://example.org/cast/atom.xml").unwrap();
+        let feed = parse_feed(ATOM, &url).unwrap();
+        assert_eq!(feed.title, "Atom Cast");
+        let episodes = feed.episodes.iter()
+            .map(|e| (e.title.as_str(), e.url.as_str(), e.published.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(episodes, [
+            // Relative to the feed, the alternate link is not an enclosure
+            ("First entry", "https://example.org/cast/media/1.ogg", Some("2024-03-05")),
+            // `published` is preferred over `updated`
+            ("Second entry", "https://cdn.example.org/2.mp3", Some("2024-02-01")),
+        ]);
+    }
+
+    #[test]
+    fn invalid_feed() {
+        let url = Url::parse("https://example.com/feed.xml").unwrap();
+        assert!(parse_feed("<rss><channel><item></channel></rss>", &url).is_err());
+        let feed = parse_feed("<rss><channel></channel></rss>", &url).unwrap();
+        assert_eq!(feed.title, "https://example.com/feed.xml");
+        assert!(feed.episodes.is_empty());
+    }
+
+    #[test]
+    fn timestamp() {
+        let cases = [
+            ("3723", Some(3723)),
+            ("62:03", Some(3723)),
+            ("01:02:03", Some(3723)),
+            ("01:02:03.500", Some(3723)),
+            (" 5 : 07 ", Some(307)),
+            ("12.9", Some(12)),
+            ("", None),
+            ("1:xx", None),
+            ("00:01:02:", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_timestamp(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn date() {
+        let cases = [
+            ("Tue, 10 Jun 2003 04:00:00 GMT", "10 Jun 2003"),
+            ("10 Jun 2003 04:00:00 +0000", "10 Jun 2003"),
+            ("2024-03-05T10:00:00Z", "2024-03-05"),
+            ("2024-03-05", "2024-03-05"),
+            ("June 2003", "June 2003"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(short_date(text), expected, "{text}");
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSource;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::meta::MetadataRevision;
+use symphonia::core::meta::StandardTagKey;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+/// Tags and duration read from an audio file
+#[derive(Debug, Clone, Default)]
+pub struct AudioTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Duration in seconds, `None` if the container doesn't tell
+    pub duration: Option<u32>,
+}
+
+/// Read the tags of an audio stream with symphonia, `extension` helps guessing the format
+///
+/// This does blocking IO, use it in `spawn_blocking()` when reading files or network streams
+pub fn probe(source: Box<dyn MediaSource>, extension: Option<&str>) -> Option<AudioTags> {
+    let mut hint = Hint::new();
+    if let Some(extension) = extension {
+        hint.with_extension(extension);
+    }
+    let stream = MediaSourceStream::new(source, Default::default());
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    let mut tags = AudioTags::default();
+    // Tags outside of the container (e.g. ID3) first, then the ones inside
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        read_revision(&mut tags, revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        read_revision(&mut tags, revision);
+    }
+    tags.duration = probed.format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        let time_base = params.time_base.or_else(|| Some(TimeBase::new(1, params.sample_rate?)))?;
+        let time = time_base.calc_time(params.n_frames?);
+        Some(time.seconds as u32)
+    });
+    Some(tags)
+}
+
+/// The beginning of a remote file, probed without downloading the rest
+///
+/// It reports the size of the whole file instead of the prefix, so durations aren't estimated from the prefix.
+/// Only MP3 sources claim to be seekable, symphonia then estimates their duration from the size when there's
+/// no Xing header. Other formats would look for the end of the file (e.g. the last OGG page), which isn't there,
+/// so their duration is only known when the header declares it (FLAC STREAMINFO, WAV, MP4).
+pub struct Prefix {
+    data: Cursor<Vec<u8>>,
+    total: Option<u64>,
+    estimate: bool,
+}
+
+impl Prefix {
+    /// `total` is the size of the whole file if known, `estimate` is set for MP3
+    pub fn new(data: Vec<u8>, total: Option<u64>, estimate: bool) -> Self {
+        Prefix { data: Cursor::new(data), total, estimate }
+    }
+}
+
+impl Read for Prefix {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl Seek for Prefix {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+impl MediaSource for Prefix {
+    fn is_seekable(&self) -> bool {
+        self.estimate && self.total.is_some()
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.total
+    }
+}
+
+fn read_revision(tags: &mut AudioTags, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        let field = match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => &mut tags.title,
+            Some(StandardTagKey::Artist) => &mut tags.artist,
+            Some(StandardTagKey::Album) => &mut tags.album,
+            _ => continue,
+        };
+        let value = tag.value.to_string();
+        if field.is_none() && !value.trim().is_empty() {
+            *field = Some(value.trim().to_string());
+        }
+    }
+}
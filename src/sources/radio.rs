@@ -0,0 +1,377 @@
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderValue;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::AudioStream;
+use songbird::input::AudioStreamError;
+use songbird::input::Compose;
+use songbird::input::HttpRequest;
+use songbird::input::Input;
+
+use symphonia::core::io::MediaSource;
+
+use tracing::instrument;
+
+use url::Url;
+
+use crate::CLIENT;
+use crate::config::CONFIG;
+use crate::structs::AudioLink;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::playlist::PlaylistFormat;
+
+/// Largest station playlist downloaded, in bytes
+const MAX_PLAYLIST_SIZE: usize = 64 * 1024;
+
+/// Icecast / SHOUTcast streams, and `.pls` / `.m3u` station playlists pointing to them
+pub struct Radio;
+
+#[async_trait]
+impl Source for Radio {
+    fn key(&self) -> &'static str {
+        "radio"
+    }
+
+    fn name(&self) -> &'static str {
+        "Internet radio"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        Ok(probe_station(url).await?.map(|info| ParseResult::Single(AudioLink::new(info))))
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        probe_station(&Url::parse(id)?).await?
+            .map(AudioLink::new)
+            .ok_or_else(|| anyhow::anyhow!("Not a radio stream: {id}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RadioInfo {
+    /// The link given by the user, either the stream or a station playlist
+    pub url: String,
+    pub stream_url: String,
+    /// `icy-name`, or the title in the station playlist
+    pub name: String,
+    pub genre: Option<String>,
+    /// Bitrate in kbps
+    pub bitrate: Option<u32>,
+    /// Bytes of audio between the metadata blocks, `None` if the server doesn't send them
+    metaint: Option<usize>,
+    /// The last `StreamTitle` received, updated while the stream is playing
+    on_air: Arc<Mutex<Option<String>>>,
+}
+
+impl RadioInfo {
+    /// The song currently on air, if the station tells
+    pub fn on_air(&self) -> Option<String> {
+        self.on_air.lock().expect("Radio title lock poisoned").clone()
+    }
+}
+
+#[async_trait]
+impl Track for RadioInfo {
+    fn source(&self) -> &'static dyn Source {
+        &Radio
+    }
+
+    fn title(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Radio streams never end
+    fn duration(&self) -> u32 {
+        0
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    fn input(&self) -> Input {
+        let mut headers = HeaderMap::new();
+        if self.metaint.is_some() {
+            headers.insert("icy-metadata", HeaderValue::from_static("1"));
+        }
+        Input::Lazy(Box::new(RadioStream {
+            request: HttpRequest::new_with_headers(CLIENT.clone(), self.stream_url.clone(), headers),
+            metaint: self.metaint,
+            on_air: self.on_air.clone(),
+        }))
+    }
+
+    fn unload(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.name)
+            .url(&self.url)
+            .field("On air", self.on_air().unwrap_or_else(|| "Unknown".to_string()), false)
+            .author(CreateEmbedAuthor::new("Audio source from internet radio"));
+        if let Some(genre) = &self.genre {
+            m = m.field("Genre", genre, true);
+        }
+        if let Some(bitrate) = self.bitrate {
+            m = m.field("Bitrate", format!("{bitrate} kbps"), true);
+        }
+        m
+    }
+}
+
+/// Check if the link is a radio stream, or a station playlist whose first stream is one,
+/// returns `None` otherwise
+#[instrument]
+pub async fn probe_station(url: &Url) -> Result<Option<RadioInfo>, ParseError> {
+    // `.m3u8` links are usually HLS streams, which list segments instead of stations
+    let station_list = PlaylistFormat::from_path(url.path())
+        .filter(|format| *format != PlaylistFormat::Xspf && !url.path().to_lowercase().ends_with(".m3u8"));
+    let (stream_url, listed_name) = match station_list {
+        Some(format) => {
+            let text = fetch_text(url).await?;
+            let Some(entry) = format.parse(&text).into_iter().find(|entry| AudioLink::is_link(&entry.location)) else {
+                return Ok(None);
+            };
+            let stream_url = Url::parse(&entry.location)?;
+            (stream_url, entry.title)
+        },
+        None => (url.clone(), None),
+    };
+
+    let response = CLIENT.get(stream_url.clone())
+        .header("icy-metadata", "1")
+        .timeout(CONFIG.http.timeout)
+        .send().await?
+        .error_for_status()?;
+    let headers = response.headers();
+    let header = |name: &str| headers.get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+
+    let is_icy = headers.keys().any(|key| key.as_str().starts_with("icy-"));
+    let is_audio = header(CONTENT_TYPE.as_str())
+        .is_some_and(|t| t.starts_with("audio/") || t.starts_with("application/ogg"));
+    let is_endless = headers.get(CONTENT_LENGTH).is_none();
+    if !(is_icy || is_audio && is_endless) {
+        return Ok(None);
+    }
+
+    Ok(Some(RadioInfo {
+        url: url.to_string(),
+        name: header("icy-name")
+            .or(listed_name)
+            .unwrap_or_else(|| stream_url.host_str().unwrap_or_default().to_string()),
+        genre: header("icy-genre"),
+        // Some servers list several bitrates, e.g. `128,128`
+        bitrate: header("icy-br").and_then(|br| br.split(',').next()?.trim().parse().ok()),
+        metaint: header("icy-metaint").and_then(|value| value.parse().ok()).filter(|n| *n > 0),
+        on_air: Arc::new(Mutex::new(None)),
+        stream_url: stream_url.to_string(),
+    }))
+}
+
+/// Download a station playlist
+async fn fetch_text(url: &Url) -> Result<String, ParseError> {
+    let mut response = CLIENT.get(url.clone())
+        .timeout(CONFIG.http.timeout)
+        .send().await?
+        .error_for_status()?;
+    let mut data = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk);
+        if data.len() > MAX_PLAYLIST_SIZE {
+            return Err(ParseError::TooLarge("The station playlist is too large".to_string()));
+        }
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// The HTTP stream of a station, with the ICY metadata blocks taken out
+struct RadioStream {
+    request: HttpRequest,
+    metaint: Option<usize>,
+    on_air: Arc<Mutex<Option<String>>>,
+}
+
+#[async_trait]
+impl Compose for RadioStream {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let stream = self.request.create_async().await?;
+        let Some(metaint) = self.metaint else {
+            return Ok(stream);
+        };
+        Ok(AudioStream {
+            input: Box::new(IcyReader {
+                inner: stream.input,
+                metaint,
+                remaining: metaint,
+                on_air: self.on_air.clone(),
+            }),
+            hint: stream.hint,
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+}
+
+/// Strips the metadata blocks inserted every `metaint` bytes, and keeps the `StreamTitle` in them
+struct IcyReader {
+    inner: Box<dyn MediaSource>,
+    metaint: usize,
+    /// Bytes of audio left before the next metadata block
+    remaining: usize,
+    on_air: Arc<Mutex<Option<String>>>,
+}
+
+impl IcyReader {
+    /// Read a metadata block, returns `false` at the end of the stream
+    fn read_metadata(&mut self) -> std::io::Result<bool> {
+        let mut len = [0];
+        if self.inner.read(&mut len)? == 0 {
+            return Ok(false);
+        }
+        // The length byte counts in 16 bytes, 0 means no update
+        let mut block = vec![0; len[0] as usize * 16];
+        self.inner.read_exact(&mut block)?;
+        if let Some(title) = stream_title(&block) {
+            *self.on_air.lock().expect("Radio title lock poisoned") = Some(title);
+        }
+        Ok(true)
+    }
+}
+
+impl Read for IcyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            if !self.read_metadata()? {
+                return Ok(0);
+            }
+            self.remaining = self.metaint;
+        }
+        let len = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+impl Seek for IcyReader {
+    fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::Error::new(ErrorKind::Unsupported, "Radio streams are not seekable"))
+    }
+}
+
+impl MediaSource for IcyReader {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Find `StreamTitle='...';` in a metadata block
+fn stream_title(block: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(block);
+    let start = text.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &text[start..];
+    let title = rest.find("';").map_or(rest, |end| &rest[..end]);
+    let title = title.trim_matches(char::from(0)).trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A metadata block padded to a multiple of 16 bytes, with its length byte
+    fn metadata(text: &str) -> Vec<u8> {
+        let mut block = text.as_bytes().to_vec();
+        block.resize(text.len().div_ceil(16) * 16, 0);
+        let mut data = vec![(block.len() / 16) as u8];
+        data.extend(block);
+        data
+    }
+
+    #[test]
+    fn title() {
+        let cases: [(&[u8], Option<&str>); 7] = [
+            (b"StreamTitle='Artist - Song';StreamUrl='';\0\0\0", Some("Artist - Song")),
+            (b"StreamUrl='x';StreamTitle='It's fine';", Some("It's fine")),
+            // Unterminated, the rest of the block is the title
+            (b"StreamTitle='Cut off\0\0\0\0", Some("Cut off")),
+            (b"StreamTitle='  Padded  ';", Some("Padded")),
+            (b"StreamTitle='';", None),
+            (b"StreamUrl='https://example.com';", None),
+            (b"", None),
+        ];
+        for (block, expected) in cases {
+            assert_eq!(stream_title(block).as_deref(), expected, "{}", String::from_utf8_lossy(block));
+        }
+    }
+
+    #[test]
+    fn icy_reader() {
+        let mut data = b"abcd".to_vec();
+        data.extend(metadata("StreamTitle='First';"));
+        data.extend(b"efgh");
+        // No update, the title is kept
+        data.push(0);
+        data.extend(b"ijkl");
+        data.extend(metadata("StreamTitle='Second';"));
+        data.extend(b"mn");
+        let on_air = Arc::new(Mutex::new(None));
+        let mut reader = IcyReader {
+            inner: Box::new(Cursor::new(data)),
+            metaint: 4,
+            remaining: 4,
+            on_air: on_air.clone(),
+        };
+        let title = || on_air.lock().unwrap().clone();
+
+        let mut buf = [0; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"abcd");
+        assert_eq!(title(), None);
+        assert_eq!(reader.read(&mut buf[..3]).unwrap(), 3);
+        assert_eq!(&buf[..3], b"efg");
+        assert_eq!(title().as_deref(), Some("First"));
+
+        let mut audio = b"efg".to_vec();
+        reader.read_to_end(&mut audio).unwrap();
+        assert_eq!(audio, b"efghijklmn");
+        assert_eq!(title().as_deref(), Some("Second"));
+    }
+}
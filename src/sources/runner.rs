@@ -0,0 +1,347 @@
+use std::process::Stdio;
+use std::string::FromUtf8Error;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
+
+use serde_json::Value;
+
+use serenity::async_trait;
+
+use songbird::input::AudioStream;
+use songbird::input::AudioStreamError;
+use songbird::input::Compose;
+use songbird::input::HlsRequest;
+use songbird::input::HttpRequest;
+use songbird::input::Input;
+use songbird::input::core::io::MediaSource;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::BufReader;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::sync::mpsc;
+
+use tracing::instrument;
+use tracing::warn;
+
+use crate::CLIENT;
+use crate::config::CONFIG;
+
+/// Caps the amount of yt-dlp processes running at the same time
+static PERMITS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(CONFIG.ytdlp.max_processes));
+
+/// Streams the media URLs found by yt-dlp, through the proxy given to yt-dlp since the URLs may be bound to its address
+static STREAM_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    let Some(proxy) = &CONFIG.ytdlp.proxy else { return CLIENT.clone() };
+    match reqwest::Proxy::all(proxy).and_then(|proxy| reqwest::Client::builder().proxy(proxy).build()) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Invalid proxy `{proxy}`, streaming without it: {e}");
+            CLIENT.clone()
+        },
+    }
+});
+
+/// Delay before the first retry, doubled on each one
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Run yt-dlp with `args` on the URL `target` and return its output,
+/// transient failures (timeouts, rate limits and network errors) are retried with backoff
+///
+/// The target always follows `--`, so a link or ID starting with `-` can't be read as an option
+pub async fn run(args: &[&str], target: &str) -> Result<String, Error> {
+    run_with(args, target, CONFIG.ytdlp.timeout, &PERMITS).await
+}
+
+/// Same as [`run`] with another time limit and process cap,
+/// for the downloads which take longer than a fetch and shouldn't hold up the lookups
+#[instrument(skip(permits))]
+pub async fn run_with(args: &[&str], target: &str, timeout: Duration, permits: &Semaphore) -> Result<String, Error> {
+    let mut attempt = 0;
+    loop {
+        match run_once(args, Some(target), timeout, permits).await {
+            Err(err) if err.is_transient() && attempt < CONFIG.ytdlp.retries => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt);
+                warn!("yt-dlp failed: {err}, retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+async fn run_once(args: &[&str], target: Option<&str>, timeout: Duration, permits: &Semaphore) -> Result<String, Error> {
+    let _permit = permits.acquire().await.expect("The semaphore is never closed");
+    let child = command(args, target).spawn()?;
+    // The process is killed when the timeout drops it
+    let output = tokio::time::timeout(timeout, child.wait_with_output()).await
+        .map_err(|_| Error::Timeout)??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!("yt-dlp exited with {}: {}", output.status, stderr.trim());
+        return Err(Error::classify(&stderr));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Run yt-dlp and send each line of its output as soon as it's printed, e.g. the items of a large playlist,
+/// the time limit applies to the wait for each line instead of the whole run
+///
+/// Stops early without error when the receiver is dropped, it's not retried as the lines may be partly sent
+#[instrument(skip(lines))]
+pub async fn run_lines(args: &[&str], target: &str, lines: mpsc::Sender<String>) -> Result<(), Error> {
+    let _permit = PERMITS.acquire().await.expect("The semaphore is never closed");
+    let mut child = command(args, Some(target)).spawn()?;
+    let stdout = child.stdout.take().expect("The output is piped");
+    let mut stderr = child.stderr.take().expect("The output is piped");
+    // Read in parallel, yt-dlp blocks when the pipe is full
+    let stderr = tokio::spawn(async move {
+        let mut text = String::new();
+        let _ = stderr.read_to_string(&mut text).await;
+        text
+    });
+
+    let mut reader = BufReader::new(stdout).lines();
+    loop {
+        match tokio::time::timeout(CONFIG.ytdlp.timeout, reader.next_line()).await {
+            Ok(Ok(Some(line))) => if lines.send(line).await.is_err() {
+                return Ok(());
+            },
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => return Err(Error::Timeout),
+        }
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        let stderr = stderr.await.unwrap_or_default();
+        warn!("yt-dlp exited with {}: {}", status, stderr.trim());
+        return Err(Error::classify(&stderr));
+    }
+    Ok(())
+}
+
+/// The yt-dlp process with the options from the config, `args`, then `target` after `--`
+fn command(args: &[&str], target: Option<&str>) -> Command {
+    let mut command = Command::new(&CONFIG.ytdlp.binary);
+    command.args(common_args())
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(target) = target {
+        command.args(["--", target]);
+    }
+    command
+}
+
+/// The options given to every run, from the config
+fn common_args() -> Vec<&'static str> {
+    let config = &CONFIG.ytdlp;
+    let mut args = vec![];
+    if let Some(cookies) = &config.cookies {
+        args.extend(["--cookies", cookies.as_str()]);
+    }
+    if let Some(proxy) = &config.proxy {
+        args.extend(["--proxy", proxy.as_str()]);
+    }
+    args.extend(config.extra_args.iter().map(String::as_str));
+    args
+}
+
+/// The version of the configured yt-dlp, checked at startup
+pub async fn version() -> Result<String, Error> {
+    Ok(run_once(&["--version"], None, CONFIG.ytdlp.timeout, &PERMITS).await?.trim().to_string())
+}
+
+/// Plays the audio of a link supported by yt-dlp,
+/// the media URL is found with the configured binary, options and format when the track starts
+#[derive(Debug, Clone)]
+pub struct YtdlpStream {
+    url: String,
+}
+
+impl YtdlpStream {
+    pub fn new(url: String) -> Self {
+        YtdlpStream { url }
+    }
+}
+
+impl From<YtdlpStream> for Input {
+    fn from(value: YtdlpStream) -> Self {
+        Input::Lazy(Box::new(value))
+    }
+}
+
+#[async_trait]
+impl Compose for YtdlpStream {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let output = run(&["-j", "--no-playlist", "--no-warning", "-f", &CONFIG.ytdlp.format], &self.url).await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        let value = output.lines().next()
+            .and_then(|line| serde_json::from_str::<Value>(line).ok())
+            .ok_or_else(|| AudioStreamError::Fail(format!("No media found for {}", self.url).into()))?;
+        let url = value["url"].as_str()
+            .ok_or_else(|| AudioStreamError::Fail(format!("No media URL for {}", self.url).into()))?
+            .to_string();
+
+        let headers = value["http_headers"].as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((
+                HeaderName::from_bytes(key.as_bytes()).ok()?,
+                HeaderValue::from_str(value.as_str()?).ok()?,
+            )))
+            .collect::<HeaderMap>();
+
+        match value["protocol"].as_str() {
+            Some("m3u8_native") => HlsRequest::new_with_headers(STREAM_CLIENT.clone(), url, headers).create(),
+            _ => {
+                let mut request = HttpRequest::new_with_headers(STREAM_CLIENT.clone(), url, headers);
+                request.content_length = value["filesize"].as_u64();
+                request.create_async().await
+            },
+        }
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to run yt-dlp: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("UTF8 error: {0}")]
+    Utf8(#[from] FromUtf8Error),
+    #[error("yt-dlp timed out")]
+    Timeout,
+    #[error("Unsupported URL")]
+    Unsupported,
+    #[error("The video is unavailable: {0}")]
+    Unavailable(String),
+    #[error("The video is private: {0}")]
+    Private(String),
+    #[error("The video is age-restricted")]
+    AgeRestricted,
+    #[error("The video is not available in the bot's country")]
+    GeoBlocked,
+    #[error("Rate limited by the site, please try again later")]
+    RateLimited,
+    /// HTTP 403, given for throttling, expired stream links and blocks as well as private videos
+    #[error("Access denied by the site: {0}")]
+    Forbidden(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("yt-dlp failed: {0}")]
+    Failed(String),
+}
+
+impl Error {
+    /// Sort the error printed by yt-dlp
+    fn classify(stderr: &str) -> Self {
+        let message = stderr.lines()
+            .rev()
+            .find_map(|line| line.trim().strip_prefix("ERROR:"))
+            .map(|line| line.trim().to_string())
+            .unwrap_or_else(|| stderr.trim().lines().last().unwrap_or("Unknown error").to_string());
+        let lower = message.to_lowercase();
+        let has = |patterns: &[&str]| patterns.iter().any(|pattern| lower.contains(pattern));
+
+        if has(&["unsupported url"]) {
+            Error::Unsupported
+        } else if has(&["confirm your age", "age-restricted", "age restricted", "inappropriate for some users"]) {
+            Error::AgeRestricted
+        } else if has(&["available in your country", "blocked it in your country", "geo restrict", "geo-restrict"]) {
+            Error::GeoBlocked
+        } else if has(&["http error 429", "too many requests", "rate limit", "rate-limit", "not a bot"]) {
+            Error::RateLimited
+        } else if has(&["private video", "members-only", "been granted access"]) {
+            Error::Private(message)
+        } else if has(&["http error 403", "403: forbidden"]) {
+            Error::Forbidden(message)
+        } else if has(&["video unavailable", "has been removed", "does not exist", "is not available", "http error 404"]) {
+            Error::Unavailable(message)
+        } else if has(&["timed out", "connection reset", "name resolution", "unable to download", "http error 5"]) {
+            Error::Network(message)
+        } else {
+            Error::Failed(message)
+        }
+    }
+
+    /// Whether running again may succeed
+    fn is_transient(&self) -> bool {
+        matches!(self, Error::Timeout | Error::RateLimited | Error::Forbidden(_) | Error::Network(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let cases = [
+            ("ERROR: Unsupported URL: https://example.com/", "Unsupported"),
+            ("ERROR: [youtube] abc: Sign in to confirm your age. This video may be inappropriate for some users.", "AgeRestricted"),
+            ("ERROR: [youtube] abc: The uploader has not made this video available in your country", "GeoBlocked"),
+            ("ERROR: [youtube] abc: Video unavailable. This video is not available in your country", "GeoBlocked"),
+            ("ERROR: [youtube] abc: Sign in to confirm you're not a bot", "RateLimited"),
+            ("ERROR: unable to download video data: HTTP Error 429: Too Many Requests", "RateLimited"),
+            ("ERROR: [youtube] abc: Private video. Sign in if you've been granted access to this video", "Private"),
+            ("ERROR: [youtube] abc: Join this channel to get access to members-only content", "Private"),
+            // 403 is also given for throttling and expired links, the video may well be public
+            ("ERROR: unable to download video data: HTTP Error 403: Forbidden", "Forbidden"),
+            ("ERROR: [generic] Unable to download webpage: HTTP Error 403: Forbidden", "Forbidden"),
+            ("ERROR: [youtube] abc: Video unavailable", "Unavailable"),
+            ("ERROR: [soundcloud] abc: Unable to download JSON metadata: HTTP Error 404: Not Found", "Unavailable"),
+            ("ERROR: [generic] Unable to download webpage: <urlopen error [Errno -3] Temporary failure in name resolution>", "Network"),
+            ("ERROR: unable to download video data: HTTP Error 503: Service Unavailable", "Network"),
+            ("WARNING: something\nERROR: Postprocessing: ffprobe not found", "Failed"),
+            ("Traceback (most recent call last):\n  boom", "Failed"),
+            ("", "Failed"),
+        ];
+        for (stderr, expected) in cases {
+            let kind = format!("{:?}", Error::classify(stderr));
+            let kind = kind.split('(').next().unwrap_or_default();
+            assert_eq!(kind, expected, "{stderr:?}");
+        }
+    }
+
+    #[test]
+    fn classify_message() {
+        let error = Error::classify("WARNING: retrying\nERROR: [youtube] abc: Video unavailable\n");
+        assert!(matches!(error, Error::Unavailable(ref message) if message == "[youtube] abc: Video unavailable"));
+        let error = Error::classify("some output\nlast line\n");
+        assert!(matches!(error, Error::Failed(ref message) if message == "last line"));
+    }
+
+    #[test]
+    fn transient() {
+        let cases = [
+            (Error::Timeout, true),
+            (Error::RateLimited, true),
+            (Error::Forbidden(String::new()), true),
+            (Error::Network(String::new()), true),
+            (Error::Private(String::new()), false),
+            (Error::Unavailable(String::new()), false),
+            (Error::Failed(String::new()), false),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.is_transient(), expected, "{error:?}");
+        }
+    }
+}
@@ -1,16 +1,98 @@
-use std::process::Stdio;
+use std::fmt::Display;
+
+use base64::prelude::*;
+
+use poise::ChoiceParameter;
 
 use serde::Deserialize;
+use serde::Serialize;
 
 use serde_json::Value;
 
-use tokio::process::Command;
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::Input;
+
+use tokio::sync::mpsc;
 
 use tracing::instrument;
+use tracing::warn;
+
+use url::Url;
 
 use urlencoding::encode;
 
 use crate::structs::AudioLink;
+use crate::structs::Metadata;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::audio_cache::AUDIO_CACHE;
+use super::fallback::Hint;
+use super::meta_cache::METADATA;
+use super::runner;
+use super::youtube_api;
+
+/// Videos and playlists on Youtube, fetched with yt-dlp
+pub struct Youtube;
+
+#[async_trait]
+impl Source for Youtube {
+    fn key(&self) -> &'static str {
+        "yt"
+    }
+
+    fn name(&self) -> &'static str {
+        "Youtube"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(
+            url.host_str(),
+            Some("www.youtube.com")
+            | Some("youtube.com")
+            | Some("m.youtube.com")
+            | Some("music.youtube.com")
+            | Some("youtu.be")
+            | Some("www.youtube-nocookie.com")
+            | Some("youtube-nocookie.com")
+        )
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        match get_yt_info(url.as_str()).await {
+            Ok(InfoType::Video(info)) => {
+                Ok(Some(ParseResult::Single(AudioLink::new(info))))
+            }
+            Ok(InfoType::Playlist(infos)) => {
+                let title = infos[0]
+                    .playlist
+                    .clone()
+                    .unwrap_or_else(|| String::from("Unknown"));
+                let list = infos
+                    .into_iter()
+                    .map(AudioLink::new)
+                    .collect();
+                Ok(Some(ParseResult::Multiple(list, Metadata { title })))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<AudioLink>, String> {
+        let list = search_yt(query, &SearchOptions::default()).await
+            .map_err(|err| format!("Search failed: {}", err))?;
+        Ok(list.into_iter().map(AudioLink::new).collect())
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        Ok(AudioLink::new(load(id).await?))
+    }
+}
 
 pub enum InfoType {
     Video(YoutubeInfo),
@@ -19,92 +101,252 @@ pub enum InfoType {
 
 #[instrument]
 pub async fn get_yt_info(url: &str) -> Result<InfoType, Error> {
-    let output = Command::new("yt-dlp")
-        .arg("-j")
-        .arg("--flat-playlist")
-        .arg("--skip-download")
-        .arg("--no-warning")
-        .arg(url)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?
-        .wait_with_output()
-        .await?;
-
-    let result = std::str::from_utf8(&output.stdout)?;
-
-    if !output.status.success() {
-        return Err(Error::CommandError(result.to_string()));
-    }
-
-    let list = result.lines()
-        .map(serde_json::from_str::<YoutubeInfo>)
-        .flatten()  // ignore the failed items
-        .collect::<Vec<_>>();
+    let key = format!("yt:info:{url}");
+    let list = match METADATA.get::<Vec<YoutubeInfo>>(&key) {
+        Some(list) => list,
+        None => {
+            let list = match from_api(youtube_api::info(url).await) {
+                Some(list) => list,
+                None => {
+                    let result = runner::run(&["-j", "--flat-playlist", "--skip-download", "--no-warning"], url).await?;
+                    result.lines()
+                        .flat_map(serde_json::from_str::<YoutubeInfo>)  // ignore the failed items
+                        .collect::<Vec<_>>()
+                },
+            };
+            if !list.is_empty() {
+                METADATA.insert(key, &list);
+                // Exported queues are loaded by the video IDs
+                for info in &list {
+                    METADATA.insert(format!("yt:{}", info.id), info);
+                }
+            }
+            list
+        },
+    };
 
     if list.len() == 1 {
         Ok(InfoType::Video(list.into_iter().next().unwrap()))
     } else if list.len() > 1 {
         Ok(InfoType::Playlist(list))
     } else {
-        Err(Error::UnknownParseError)
+        Err(Error::UnknownParse)
     }
 }
 
+/// Fetch a video by its ID, e.g. from an exported queue
 #[instrument]
-pub async fn load(url: &str) -> Result<YoutubeInfo, Error> {
-    let output = Command::new("yt-dlp")
-        .arg("-j")
-        .arg("--skip-download")
-        .arg("--no-warning")
-        .arg(url)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?
-        .wait_with_output()
-        .await?;
+pub async fn load(id: &str) -> Result<YoutubeInfo, Error> {
+    if !is_video_id(id) {
+        return Err(Error::InvalidId(id.to_string()));
+    }
+    let key = format!("yt:{id}");
+    if let Some(info) = METADATA.get(&key) {
+        return Ok(info);
+    }
+    let info = match from_api(youtube_api::video(id).await) {
+        Some(info) => info,
+        None => {
+            let result = runner::run(&["-j", "--skip-download", "--no-warning"], &YoutubeLink::video_url(id)).await?;
+            let item = result.lines().next().ok_or(Error::UnknownParse)?;
+            serde_json::from_str::<YoutubeInfo>(item)?
+        },
+    };
+    METADATA.insert(key, &info);
+    Ok(info)
+}
 
-    let result = std::str::from_utf8(&output.stdout)?;
 
-    if !output.status.success() {
-        return Err(Error::CommandError(result.to_string()));
+/// Video IDs are 11 characters of the URL-safe base64 alphabet
+pub fn is_video_id(id: &str) -> bool {
+    id.len() == 11 && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Fetch the videos of a playlist and send each one as soon as it's read,
+/// so the first songs can be played before a large playlist is fully loaded
+#[instrument(skip(videos))]
+pub async fn stream_playlist(list_id: &str, range: ItemRange, videos: mpsc::Sender<YoutubeInfo>) -> Result<(), Error> {
+    let url = format!("https://www.youtube.com/playlist?list={list_id}");
+    let key = format!("yt:info:{url}");
+    let known = match METADATA.get::<Vec<YoutubeInfo>>(&key) {
+        Some(list) => Some(list),
+        None => from_api(youtube_api::playlist(list_id).await),
+    };
+    if let Some(list) = known {
+        for info in range.slice(list) {
+            if videos.send(info).await.is_err() {
+                break;
+            }
+        }
+        return Ok(());
     }
 
-    let item = result.lines().next().ok_or(Error::UnknownParseError)?;
+    let (line_tx, mut line_rx) = mpsc::channel::<String>(STREAM_BUFFER);
+    let items = range.to_string();
+    let args = [
+        "-j",
+        "--flat-playlist",
+        "--skip-download",
+        "--no-warning",
+        "--playlist-items",
+        &items,
+    ];
+    let forward = async move {
+        let mut list = vec![];
+        while let Some(line) = line_rx.recv().await {
+            // ignore the failed items
+            let Ok(info) = serde_json::from_str::<YoutubeInfo>(&line) else { continue };
+            METADATA.insert(format!("yt:{}", info.id), &info);
+            list.push(info.clone());
+            if videos.send(info).await.is_err() {
+                break;
+            }
+        }
+        list
+    };
+    let (result, list) = tokio::join!(runner::run_lines(&args, &url, line_tx), forward);
+    result?;
+    if range.is_full() && !list.is_empty() {
+        METADATA.insert(key, &list);
+    }
+    Ok(())
+}
 
-    Ok(serde_json::from_str::<YoutubeInfo>(item)?)
+/// Videos buffered while streaming a playlist
+const STREAM_BUFFER: usize = 64;
+
+/// Items of a playlist to load, 1-based and inclusive like the `--playlist-items` of yt-dlp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl ItemRange {
+    pub const ALL: ItemRange = ItemRange { start: 1, end: None };
+
+    /// Parse `20-80` or `20-`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (start, end) = text.split_once('-')?;
+        let start = start.parse::<usize>().ok().filter(|start| *start > 0)?;
+        let end = match end {
+            "" => None,
+            end => Some(end.parse::<usize>().ok().filter(|end| *end >= start)?),
+        };
+        Some(ItemRange { start, end })
+    }
+
+    fn is_full(&self) -> bool {
+        *self == Self::ALL
+    }
+
+    fn slice<T>(&self, list: Vec<T>) -> impl Iterator<Item = T> {
+        let count = match self.end {
+            Some(end) => end + 1 - self.start,
+            None => usize::MAX,
+        };
+        list.into_iter().skip(self.start - 1).take(count)
+    }
 }
 
+impl Display for ItemRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}:{}", self.start, end),
+            None => write!(f, "{}:", self.start),
+        }
+    }
+}
+
+/// The kinds of Youtube links, told apart by the IDs they carry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YoutubeLink {
+    Video(String),
+    Playlist(String),
+    /// A video played from a playlist, `watch?v=<video>&list=<list>`
+    VideoInList {
+        video: String,
+        list: String,
+    },
+}
+
+impl YoutubeLink {
+    pub fn parse(url: &Url) -> Option<Self> {
+        if !Youtube.matches(url) {
+            return None;
+        }
+        let query = |name: &str| url.query_pairs()
+            .find(|(key, value)| key == name && !value.is_empty())
+            .map(|(_, value)| value.into_owned());
+        let mut segments = url.path_segments()?;
+        let video = match (url.host_str()?, segments.next()) {
+            ("youtu.be", id) => id,
+            (_, Some("shorts" | "live" | "embed" | "v")) => segments.next(),
+            _ => None,
+        };
+        // `/embed/videoseries?list=` embeds a playlist
+        let video = video
+            .filter(|id| !id.is_empty() && *id != "videoseries")
+            .map(str::to_string)
+            .or_else(|| query("v"));
+        match (video, query("list")) {
+            (Some(video), Some(list)) => Some(YoutubeLink::VideoInList { video, list }),
+            (Some(video), None) => Some(YoutubeLink::Video(video)),
+            (None, Some(list)) => Some(YoutubeLink::Playlist(list)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn video_url(id: &str) -> String {
+        format!("https://www.youtube.com/watch?v={id}")
+    }
 
+    /// The usual form of the link, without the parameters other than the IDs
+    pub fn url(&self) -> String {
+        match self {
+            YoutubeLink::Video(id) => Self::video_url(id),
+            YoutubeLink::Playlist(list) => format!("https://www.youtube.com/playlist?list={list}"),
+            YoutubeLink::VideoInList { video, list } => format!("https://www.youtube.com/watch?v={video}&list={list}"),
+        }
+    }
+}
+
+/// Search for videos, shorts are excluded
+#[instrument]
+pub async fn search_yt(prompt: &str, options: &SearchOptions) -> Result<Vec<YoutubeInfo>, Error> {
+    let key = format!(
+        "yt:search:{}:{}:{}",
+        options.search_params(ResultKind::Video),
+        options.match_filter(ResultKind::Video),
+        prompt.trim().to_lowercase(),
+    );
+    if let Some(list) = METADATA.get(&key) {
+        return Ok(list);
+    }
+    let list = match from_api(youtube_api::search(prompt, options).await) {
+        Some(list) => list,
+        None => videos(search_raw(prompt, options, ResultKind::Video, SEARCH_COUNT).await?),
+    };
+    // An empty result may be a transient failure, don't keep it for the whole lifetime
+    if !list.is_empty() {
+        METADATA.insert(key, &list);
+    }
+    Ok(list)
+}
+
+/// Search for a few videos, for the autocomplete which has to answer within 3 seconds
 #[instrument]
-pub async fn search_yt(prompt: &str) -> Result<Vec<YoutubeInfo>, Error> {
-    let output = Command::new("yt-dlp")
-        .arg("-j")
-        .arg("--flat-playlist")
-        .arg("--skip-download")
-        .arg("--no-warning")
-        .arg("--match-filter")
-        .arg("original_url!*=/shorts/ & url!*=/shorts/")
-        .arg("--playlist-items")
-        .arg("1:70")
-        .arg(format!("https://www.youtube.com/results?sp=CAASAhAB&search_query={}", encode(prompt)))
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?
-        .wait_with_output()
-        .await?;
-
-    let result = std::str::from_utf8(&output.stdout)?;
-
-    if !output.status.success() {
-        return Err(Error::CommandError(result.to_string()));
-    }
-
-    let list = result.lines()
-        .flat_map(serde_json::from_str::<Value>)
+pub async fn suggest_yt(prompt: &str) -> Result<Vec<YoutubeInfo>, Error> {
+    let options = SearchOptions::default();
+    if let Some(list) = from_api(youtube_api::search(prompt, &options).await) {
+        return Ok(list);
+    }
+    Ok(videos(search_raw(prompt, &options, ResultKind::Video, SUGGESTION_COUNT).await?))
+}
+
+/// Read the videos of a flat search, live streams without a duration are left out
+fn videos(values: Vec<Value>) -> Vec<YoutubeInfo> {
+    values.into_iter()
         .flat_map(|mut v| {
             if let Value::Object(ref mut map) = v {
                 let r = map.get_mut("duration")?;
@@ -118,12 +360,242 @@ pub async fn search_yt(prompt: &str) -> Result<Vec<YoutubeInfo>, Error> {
             None
         })
         .flat_map(serde_json::from_value::<YoutubeInfo>)
+        .collect()
+}
+
+/// Search for playlists, the duration filter doesn't apply
+#[instrument]
+pub async fn search_yt_playlist(prompt: &str, options: &SearchOptions) -> Result<Vec<PlaylistInfo>, Error> {
+    if let Some(list) = from_api(youtube_api::search_playlist(prompt, options).await) {
+        return Ok(list);
+    }
+    let list = search_raw(prompt, options, ResultKind::Playlist, SEARCH_COUNT).await?
+        .into_iter()
+        .flat_map(serde_json::from_value::<PlaylistInfo>)
         .collect::<Vec<_>>();
 
     Ok(list)
 }
 
+/// Take the result of the HTTP API, `None` falls back to yt-dlp
+pub(super) fn from_api<T>(result: Result<T, youtube_api::Error>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(youtube_api::Error::Disabled) => None,
+        Err(e) => {
+            warn!("Youtube API failed, falling back to yt-dlp: {e}");
+            None
+        },
+    }
+}
+
+/// Results read by a search
+const SEARCH_COUNT: usize = 70;
+
+/// Results read for the autocomplete, fewer so it answers in time
+const SUGGESTION_COUNT: usize = 10;
+
+async fn search_raw(prompt: &str, options: &SearchOptions, kind: ResultKind, count: usize) -> Result<Vec<Value>, Error> {
+    let match_filter = options.match_filter(kind);
+    let items = format!("1:{count}");
+    let url = format!(
+        "https://www.youtube.com/results?sp={}&search_query={}",
+        encode(&options.search_params(kind)),
+        encode(prompt),
+    );
+    let result = runner::run(&[
+        "-j",
+        "--flat-playlist",
+        "--skip-download",
+        "--no-warning",
+        "--match-filter",
+        &match_filter,
+        "--playlist-items",
+        &items,
+    ], &url).await?;
+
+    Ok(result.lines().flat_map(serde_json::from_str::<Value>).collect())
+}
+
+/// Filters and ordering of a Youtube search
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    pub duration: Option<DurationFilter>,
+    pub upload_date: Option<UploadDate>,
+    pub sort: Option<SortOrder>,
+    /// Only keep results uploaded by channels containing this name (case insensitive)
+    pub channel: Option<String>,
+}
+
+/// The choices derive `ChoiceParameter` for the case insensitive `from_name()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum DurationFilter {
+    #[name = "short"]
+    Short,
+    #[name = "medium"]
+    Medium,
+    #[name = "long"]
+    Long,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum UploadDate {
+    #[name = "hour"]
+    Hour,
+    #[name = "today"]
+    Today,
+    #[name = "week"]
+    Week,
+    #[name = "month"]
+    Month,
+    #[name = "year"]
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum SortOrder {
+    #[name = "relevance"]
+    Relevance,
+    #[name = "date"]
+    #[name = "upload_date"]
+    UploadDate,
+    #[name = "views"]
+    #[name = "view_count"]
+    ViewCount,
+    #[name = "rating"]
+    Rating,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ResultKind {
+    #[name = "video"]
+    Video,
+    #[name = "playlist"]
+    Playlist,
+}
+
+impl SearchOptions {
+    /// Take the `key:value` filters out of the prompt, e.g. `lofi duration:long channel:"Lofi Girl"`
+    ///
+    /// Returns the options, the result type and the remaining prompt
+    pub fn extract(prompt: &str) -> (Self, Option<ResultKind>, String) {
+        let mut options = Self::default();
+        let mut kind = None;
+        let mut rest = vec![];
+        let mut tokens = prompt.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let Some((key, value)) = token.split_once(':') else {
+                rest.push(token.to_string());
+                continue;
+            };
+            match key.to_lowercase().as_str() {
+                "duration" if DurationFilter::from_name(value).is_some() => options.duration = DurationFilter::from_name(value),
+                "date" | "uploaded" if UploadDate::from_name(value).is_some() => options.upload_date = UploadDate::from_name(value),
+                "sort" if SortOrder::from_name(value).is_some() => options.sort = SortOrder::from_name(value),
+                "type" if ResultKind::from_name(value).is_some() => kind = ResultKind::from_name(value),
+                "channel" if !value.is_empty() => {
+                    let mut channel = value.to_string();
+                    if let Some(quoted) = value.strip_prefix('"') {
+                        channel = quoted.to_string();
+                        while !channel.ends_with('"') {
+                            match tokens.next() {
+                                Some(token) => channel = channel + " " + token,
+                                None => break,
+                            }
+                        }
+                        channel = channel.trim_end_matches('"').to_string();
+                    }
+                    options.channel = Some(channel);
+                },
+                _ => rest.push(token.to_string()),
+            }
+        }
+        (options, kind, rest.join(" "))
+    }
+
+    /// The `sp` query parameter of the search page, a base64 encoded protobuf message
+    fn search_params(&self, kind: ResultKind) -> String {
+        let sort = match self.sort.unwrap_or(SortOrder::Relevance) {
+            SortOrder::Relevance => 0,
+            SortOrder::Rating => 1,
+            SortOrder::UploadDate => 2,
+            SortOrder::ViewCount => 3,
+        };
+        let mut filter = vec![];
+        if let Some(date) = self.upload_date {
+            filter.extend([0x08, match date {
+                UploadDate::Hour => 1,
+                UploadDate::Today => 2,
+                UploadDate::Week => 3,
+                UploadDate::Month => 4,
+                UploadDate::Year => 5,
+            }]);
+        }
+        filter.extend([0x10, match kind {
+            ResultKind::Video => 1,
+            ResultKind::Playlist => 3,
+        }]);
+        if let (Some(duration), ResultKind::Video) = (self.duration, kind) {
+            filter.extend([0x18, match duration {
+                DurationFilter::Short => 1,
+                DurationFilter::Long => 2,
+                DurationFilter::Medium => 3,
+            }]);
+        }
+        let mut message = vec![0x08, sort, 0x12, filter.len() as u8];
+        message.extend(filter);
+        BASE64_STANDARD.encode(message)
+    }
+
+    /// The `--match-filter` of yt-dlp, double checks what the search page may not respect
+    fn match_filter(&self, kind: ResultKind) -> String {
+        let mut filters = vec!["original_url!*=/shorts/".to_string(), "url!*=/shorts/".to_string()];
+        if let (Some(duration), ResultKind::Video) = (self.duration, kind) {
+            filters.push(match duration {
+                DurationFilter::Short => "duration<240".to_string(),
+                DurationFilter::Medium => "duration>=240 & duration<=1200".to_string(),
+                DurationFilter::Long => "duration>1200".to_string(),
+            });
+        }
+        if let Some(channel) = &self.channel {
+            let pattern = channel.chars()
+                .map(|c| if c.is_alphanumeric() || c == ' ' { c.to_string() } else { format!("\\{c}") })
+                .collect::<String>();
+            filters.push(format!("channel~='(?i){pattern}'"));
+        }
+        filters.join(" & ")
+    }
+}
+
+impl Display for SearchOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut items = vec![];
+        if let Some(duration) = self.duration {
+            items.push(format!("Duration: `{}`", duration.name()));
+        }
+        if let Some(date) = self.upload_date {
+            items.push(format!("Uploaded: `{}`", date.name()));
+        }
+        if let Some(sort) = self.sort {
+            items.push(format!("Sort by: `{}`", sort.name()));
+        }
+        if let Some(channel) = &self.channel {
+            items.push(format!("Channel: `{}`", channel));
+        }
+        write!(f, "{}", items.join(", "))
+    }
+}
+
+/// A playlist in the search result
 #[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistInfo {
+    pub title: String,
+    pub url: String,
+    pub channel: Option<String>,
+    pub playlist_count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct YoutubeInfo {
     pub id: String,
     pub title: String,
@@ -134,40 +606,193 @@ pub struct YoutubeInfo {
     pub playlist: Option<String>,
 }
 
+impl YoutubeInfo {
+    pub fn url(&self) -> String {
+        YoutubeLink::video_url(&self.id)
+    }
+}
+
+#[async_trait]
+impl Track for YoutubeInfo {
+    fn source(&self) -> &'static dyn Source {
+        &Youtube
+    }
+
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    fn video(&self) -> Option<&YoutubeInfo> {
+        Some(self)
+    }
+
+    fn hint(&self) -> Option<Hint> {
+        Some(Hint::from_video(self))
+    }
+
+    fn input(&self) -> Input {
+        if let Some(path) = AUDIO_CACHE.get(&self.id) {
+            return songbird::input::File::new(path).into();
+        }
+        AUDIO_CACHE.fetch(self);
+        runner::YtdlpStream::new(self.url()).into()
+    }
+
+    fn unload(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.title)
+            .url(self.url())
+            .field("Channel", &self.channel, true)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new("Audio source from Youtube"));
+        if let Some(desc) = &self.description {
+            m = m.description(desc);
+        }
+        if let Some(playlist) = &self.playlist {
+            m = m.field("Playlist", playlist, true);
+        }
+        m.field("Channel URL", &self.channel_url, false)
+    }
+}
+
 impl From<YoutubeInfo> for AudioLink {
     fn from(value: YoutubeInfo) -> Self {
-        AudioLink::Youtube(value)
+        AudioLink::new(value)
     }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("serde_json error: {0}")]
-    SerdeJsonError(serde_json::Error),
-    #[error("std::io error: {0}")]
-    StdIOError(std::io::Error),
-    #[error("UTF8 error: {0}")]
-    Utf8Error(std::str::Utf8Error),
-    #[error("Command error: {0}")]
-    CommandError(String),
+    SerdeJson(serde_json::Error),
+    #[error(transparent)]
+    Ytdlp(#[from] runner::Error),
     #[error("Unknown parser error")]
-    UnknownParseError,
+    UnknownParse,
+    #[error("`{0}` is not a Youtube video ID")]
+    InvalidId(String),
 }
 
 impl From<serde_json::Error> for Error {
     fn from(value: serde_json::Error) -> Self {
-        Error::SerdeJsonError(value)
+        Error::SerdeJson(value)
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(value: std::io::Error) -> Self {
-        Error::StdIOError(value)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(link: &str) -> Option<YoutubeLink> {
+        YoutubeLink::parse(&Url::parse(link).expect("valid URL"))
+    }
+
+    fn video(id: &str) -> Option<YoutubeLink> {
+        Some(YoutubeLink::Video(id.to_string()))
+    }
+
+    #[test]
+    fn youtube_link() {
+        let cases = [
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ", video("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&si=abc&t=42", video("dQw4w9WgXcQ")),
+            ("https://m.youtube.com/watch?v=dQw4w9WgXcQ", video("dQw4w9WgXcQ")),
+            ("https://music.youtube.com/watch?v=dQw4w9WgXcQ&feature=share", video("dQw4w9WgXcQ")),
+            ("https://youtu.be/dQw4w9WgXcQ?si=abc", video("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/shorts/dQw4w9WgXcQ", video("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ?feature=shared", video("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/embed/dQw4w9WgXcQ", video("dQw4w9WgXcQ")),
+            ("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ", video("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/v/dQw4w9WgXcQ", video("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/playlist?list=PL123", Some(YoutubeLink::Playlist("PL123".to_string()))),
+            ("https://www.youtube.com/embed/videoseries?list=PL123", Some(YoutubeLink::Playlist("PL123".to_string()))),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123&index=3", Some(YoutubeLink::VideoInList {
+                video: "dQw4w9WgXcQ".to_string(),
+                list: "PL123".to_string(),
+            })),
+            ("https://www.youtube.com/watch?v=&list=", None),
+            ("https://www.youtube.com/@channel", None),
+            ("https://youtu.be/", None),
+            ("https://example.com/watch?v=dQw4w9WgXcQ", None),
+        ];
+        for (link, expected) in cases {
+            assert_eq!(parse(link), expected, "{link}");
+        }
+    }
+
+    #[test]
+    fn youtube_link_url() {
+        let cases = [
+            (YoutubeLink::Video("dQw4w9WgXcQ".to_string()), "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            (YoutubeLink::Playlist("PL123".to_string()), "https://www.youtube.com/playlist?list=PL123"),
+            (YoutubeLink::VideoInList { video: "dQw4w9WgXcQ".to_string(), list: "PL123".to_string() },
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123"),
+        ];
+        for (link, expected) in cases {
+            assert_eq!(link.url(), expected);
+            assert_eq!(parse(expected), Some(link), "{expected}");
+        }
+    }
+
+    #[test]
+    fn item_range() {
+        let cases = [
+            ("20-80", Some(ItemRange { start: 20, end: Some(80) })),
+            ("20-", Some(ItemRange { start: 20, end: None })),
+            ("1-", Some(ItemRange::ALL)),
+            ("5-5", Some(ItemRange { start: 5, end: Some(5) })),
+            ("80-20", None),
+            ("0-10", None),
+            ("-10", None),
+            ("20", None),
+            ("a-b", None),
+            ("", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(ItemRange::parse(text), expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn item_range_slice() {
+        let list = (1..=10).collect::<Vec<_>>();
+        let cases = [
+            (ItemRange::ALL, (1..=10).collect::<Vec<_>>()),
+            (ItemRange { start: 3, end: Some(5) }, vec![3, 4, 5]),
+            (ItemRange { start: 9, end: None }, vec![9, 10]),
+            (ItemRange { start: 8, end: Some(20) }, vec![8, 9, 10]),
+            (ItemRange { start: 11, end: None }, vec![]),
+        ];
+        for (range, expected) in cases {
+            assert_eq!(range.slice(list.clone()).collect::<Vec<_>>(), expected, "{range}");
+        }
     }
-}
 
-impl From<std::str::Utf8Error> for Error {
-    fn from(value: std::str::Utf8Error) -> Self {
-        Error::Utf8Error(value)
+    #[test]
+    fn search_params() {
+        let options = |duration, upload_date, sort| SearchOptions { duration, upload_date, sort, channel: None };
+        // The same messages as the filters of the search page, with the sort order always set
+        let cases = [
+            (options(None, None, None), ResultKind::Video, "CAASAhAB"),
+            (options(None, None, Some(SortOrder::UploadDate)), ResultKind::Video, "CAISAhAB"),
+            (options(None, None, Some(SortOrder::ViewCount)), ResultKind::Video, "CAMSAhAB"),
+            (options(None, Some(UploadDate::Week), None), ResultKind::Video, "CAASBAgDEAE="),
+            (options(Some(DurationFilter::Short), None, None), ResultKind::Video, "CAASBBABGAE="),
+            (options(Some(DurationFilter::Long), Some(UploadDate::Year), Some(SortOrder::Rating)), ResultKind::Video, "CAESBggFEAEYAg=="),
+            (options(None, None, None), ResultKind::Playlist, "CAASAhAD"),
+            // Playlists have no duration
+            (options(Some(DurationFilter::Medium), Some(UploadDate::Hour), None), ResultKind::Playlist, "CAASBAgBEAM="),
+        ];
+        for (options, kind, expected) in cases {
+            assert_eq!(options.search_params(kind), expected, "{options:?} {kind:?}");
+        }
     }
 }
@@ -0,0 +1,404 @@
+use serde_json::Value;
+
+use tracing::instrument;
+
+use url::Url;
+
+use crate::CLIENT;
+use crate::config::ApiKind;
+use crate::config::CONFIG;
+use crate::config::YoutubeApi;
+
+use super::youtube::DurationFilter;
+use super::youtube::PlaylistInfo;
+use super::youtube::ResultKind;
+use super::youtube::SearchOptions;
+use super::youtube::SortOrder;
+use super::youtube::UploadDate;
+use super::youtube::YoutubeInfo;
+use super::youtube::YoutubeLink;
+
+/// Pages read from a playlist at most, Invidious lists 100 videos per page
+const MAX_PLAYLIST_PAGES: usize = 50;
+
+/// Fetch a video or the videos of a playlist, like `yt-dlp --flat-playlist`
+#[instrument]
+pub async fn info(url: &str) -> Result<Vec<YoutubeInfo>, Error> {
+    let url = Url::parse(url).map_err(|_| Error::Unsupported)?;
+    match YoutubeLink::parse(&url).ok_or(Error::Unsupported)? {
+        YoutubeLink::Video(id) => Ok(vec![video(&id).await?]),
+        YoutubeLink::Playlist(list) | YoutubeLink::VideoInList { list, .. } => playlist(&list).await,
+    }
+}
+
+#[instrument]
+pub async fn video(id: &str) -> Result<YoutubeInfo, Error> {
+    fetch_video(api()?, id).await
+}
+
+async fn fetch_video(api: &YoutubeApi, id: &str) -> Result<YoutubeInfo, Error> {
+    let (kind, base) = (api.kind, &api.url);
+    let value = match kind {
+        ApiKind::Invidious => get(&format!("{base}/api/v1/videos/{id}"), &[]).await?,
+        ApiKind::Piped => get(&format!("{base}/streams/{id}"), &[]).await?,
+    };
+    video_info(kind, &value, Some(id)).ok_or(Error::Malformed)
+}
+
+#[instrument]
+pub async fn playlist(id: &str) -> Result<Vec<YoutubeInfo>, Error> {
+    fetch_playlist(api()?, id).await
+}
+
+async fn fetch_playlist(api: &YoutubeApi, id: &str) -> Result<Vec<YoutubeInfo>, Error> {
+    let (kind, base) = (api.kind, &api.url);
+    let mut list = vec![];
+    match kind {
+        ApiKind::Invidious => {
+            for page in 1..=MAX_PLAYLIST_PAGES {
+                let value = get(&format!("{base}/api/v1/playlists/{id}"), &[("page", page.to_string())]).await?;
+                let title = value["title"].as_str();
+                let videos = value["videos"].as_array().cloned().unwrap_or_default();
+                let count = list.len();
+                list.extend(videos.iter().filter_map(|v| video_info(kind, v, None)).map(|mut info| {
+                    info.playlist = title.map(str::to_string);
+                    info
+                }));
+                let total = value["videoCount"].as_u64().unwrap_or_default() as usize;
+                if list.len() == count || list.len() >= total {
+                    break;
+                }
+            }
+        },
+        ApiKind::Piped => {
+            let mut value = get(&format!("{base}/playlists/{id}"), &[]).await?;
+            let title = value["name"].as_str().map(str::to_string);
+            for _ in 0..MAX_PLAYLIST_PAGES {
+                let videos = value["relatedStreams"].as_array().cloned().unwrap_or_default();
+                list.extend(videos.iter().filter_map(|v| video_info(kind, v, None)).map(|mut info| {
+                    info.playlist = title.clone();
+                    info
+                }));
+                let Some(next) = value["nextpage"].as_str().filter(|next| !next.is_empty()) else { break };
+                value = get(&format!("{base}/nextpage/playlists/{id}"), &[("nextpage", next.to_string())]).await?;
+            }
+        },
+    }
+    if list.is_empty() {
+        return Err(Error::Malformed);
+    }
+    Ok(list)
+}
+
+/// Search for videos, options Piped can't apply are left to yt-dlp
+#[instrument]
+pub async fn search(prompt: &str, options: &SearchOptions) -> Result<Vec<YoutubeInfo>, Error> {
+    fetch_search(api()?, prompt, options).await
+}
+
+async fn fetch_search(api: &YoutubeApi, prompt: &str, options: &SearchOptions) -> Result<Vec<YoutubeInfo>, Error> {
+    let kind = api.kind;
+    let list = search_raw(api, prompt, options, ResultKind::Video).await?
+        .iter()
+        .filter(|item| match kind {
+            ApiKind::Invidious => item["type"] == "video",
+            ApiKind::Piped => item["type"] == "stream" && item["isShort"] != true,
+        })
+        .filter_map(|item| video_info(kind, item, None))
+        // Live streams have no duration
+        .filter(|info| info.duration > 0)
+        .filter(|info| options.duration.is_none_or(|duration| match duration {
+            DurationFilter::Short => info.duration < 240,
+            DurationFilter::Medium => (240..=1200).contains(&info.duration),
+            DurationFilter::Long => info.duration > 1200,
+        }))
+        .filter(|info| matches_channel(options, &info.channel))
+        .collect();
+    Ok(list)
+}
+
+#[instrument]
+pub async fn search_playlist(prompt: &str, options: &SearchOptions) -> Result<Vec<PlaylistInfo>, Error> {
+    fetch_search_playlist(api()?, prompt, options).await
+}
+
+async fn fetch_search_playlist(api: &YoutubeApi, prompt: &str, options: &SearchOptions) -> Result<Vec<PlaylistInfo>, Error> {
+    let kind = api.kind;
+    let list = search_raw(api, prompt, options, ResultKind::Playlist).await?
+        .iter()
+        .filter_map(|item| match kind {
+            ApiKind::Invidious if item["type"] == "playlist" => Some(PlaylistInfo {
+                title: item["title"].as_str()?.to_string(),
+                url: format!("https://www.youtube.com/playlist?list={}", item["playlistId"].as_str()?),
+                channel: item["author"].as_str().map(str::to_string),
+                playlist_count: item["videoCount"].as_u64().map(|count| count as u32),
+            }),
+            ApiKind::Piped if item["type"] == "playlist" => Some(PlaylistInfo {
+                title: item["name"].as_str()?.to_string(),
+                url: format!("https://www.youtube.com{}", item["url"].as_str()?),
+                channel: item["uploaderName"].as_str().map(str::to_string),
+                playlist_count: item["videos"].as_u64().map(|count| count as u32),
+            }),
+            _ => None,
+        })
+        .filter(|info| matches_channel(options, info.channel.as_deref().unwrap_or_default()))
+        .collect();
+    Ok(list)
+}
+
+async fn search_raw(api: &YoutubeApi, prompt: &str, options: &SearchOptions, kind: ResultKind) -> Result<Vec<Value>, Error> {
+    let (api_kind, base) = (api.kind, &api.url);
+    let value = match api_kind {
+        ApiKind::Invidious => {
+            let mut query = vec![
+                ("q", prompt.to_string()),
+                ("type", match kind {
+                    ResultKind::Video => "video",
+                    ResultKind::Playlist => "playlist",
+                }.to_string()),
+            ];
+            if let Some(sort) = options.sort {
+                query.push(("sort", match sort {
+                    SortOrder::Relevance => "relevance",
+                    SortOrder::Rating => "rating",
+                    SortOrder::UploadDate => "upload_date",
+                    SortOrder::ViewCount => "view_count",
+                }.to_string()));
+            }
+            if let Some(date) = options.upload_date {
+                query.push(("date", match date {
+                    UploadDate::Hour => "hour",
+                    UploadDate::Today => "today",
+                    UploadDate::Week => "week",
+                    UploadDate::Month => "month",
+                    UploadDate::Year => "year",
+                }.to_string()));
+            }
+            get(&format!("{base}/api/v1/search"), &query).await?
+        },
+        ApiKind::Piped => {
+            if options.sort.is_some() || options.upload_date.is_some() {
+                return Err(Error::Unsupported);
+            }
+            let filter = match kind {
+                ResultKind::Video => "videos",
+                ResultKind::Playlist => "playlists",
+            };
+            let value = get(&format!("{base}/search"), &[("q", prompt.to_string()), ("filter", filter.to_string())]).await?;
+            value["items"].clone()
+        },
+    };
+    match value {
+        Value::Array(items) => Ok(items),
+        _ => Err(Error::Malformed),
+    }
+}
+
+/// The configured instance
+fn api() -> Result<&'static YoutubeApi, Error> {
+    CONFIG.youtube.api.as_ref().ok_or(Error::Disabled)
+}
+
+async fn get(url: &str, query: &[(&str, String)]) -> Result<Value, Error> {
+    let data = CLIENT.get(url)
+        .query(query)
+        .timeout(CONFIG.youtube.api_timeout)
+        .send().await?
+        .error_for_status()?
+        .bytes().await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Read a video from the API response, `id` is given when the response doesn't carry it
+fn video_info(kind: ApiKind, value: &Value, id: Option<&str>) -> Option<YoutubeInfo> {
+    let (id, title, description, channel, channel_path, duration) = match kind {
+        ApiKind::Invidious => (
+            value["videoId"].as_str().or(id)?,
+            value["title"].as_str()?,
+            value["description"].as_str(),
+            value["author"].as_str()?,
+            value["authorUrl"].as_str().unwrap_or_default(),
+            value["lengthSeconds"].as_u64().unwrap_or_default(),
+        ),
+        ApiKind::Piped => (
+            value["url"].as_str()
+                .and_then(|url| url.strip_prefix("/watch?v="))
+                .or(id)?,
+            value["title"].as_str()?,
+            value["shortDescription"].as_str().or(value["description"].as_str()),
+            value["uploaderName"].as_str().or(value["uploader"].as_str())?,
+            value["uploaderUrl"].as_str().unwrap_or_default(),
+            value["duration"].as_i64().unwrap_or_default().max(0) as u64,
+        ),
+    };
+    Some(YoutubeInfo {
+        id: id.to_string(),
+        title: title.to_string(),
+        description: description.filter(|desc| !desc.is_empty()).map(str::to_string),
+        channel: channel.to_string(),
+        // The APIs give paths like `/channel/<id>`
+        channel_url: format!("https://www.youtube.com{channel_path}"),
+        duration: duration as u32,
+        playlist: None,
+    })
+}
+
+fn matches_channel(options: &SearchOptions, channel: &str) -> bool {
+    options.channel.as_ref()
+        .is_none_or(|filter| channel.to_lowercase().contains(&filter.to_lowercase()))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("No Youtube API is configured")]
+    Disabled,
+    #[error("Not supported by the Youtube API")]
+    Unsupported,
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Unexpected response from the Youtube API")]
+    Malformed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::mock_server::MockServer;
+    use super::super::youtube::from_api;
+
+    fn instance(kind: ApiKind, server: &MockServer) -> YoutubeApi {
+        YoutubeApi { kind, url: server.url.clone() }
+    }
+
+    fn ids(list: &[YoutubeInfo]) -> Vec<&str> {
+        list.iter().map(|info| info.id.as_str()).collect()
+    }
+
+    const INVIDIOUS_VIDEO: &str = r#"{"type": "video", "videoId": "dQw4w9WgXcQ", "title": "Never Gonna Give You Up",
+        "description": "", "author": "Rick Astley", "authorUrl": "/channel/UCuAXFkgsw1L7xaCfnd5JJOw", "lengthSeconds": 213}"#;
+
+    fn piped_stream(id: &str, duration: i64) -> String {
+        format!(r#"{{"type": "stream", "url": "/watch?v={id}", "title": "Video {id}", "uploaderName": "Channel",
+            "uploaderUrl": "/channel/UC1", "duration": {duration}, "isShort": false}}"#)
+    }
+
+    #[tokio::test]
+    async fn invidious() {
+        let page = |videos: &str| format!(r#"{{"title": "Mix", "videoCount": 3, "videos": [{videos}]}}"#);
+        let second = INVIDIOUS_VIDEO.replace("dQw4w9WgXcQ", "AyOqGRjVtls");
+        let third = INVIDIOUS_VIDEO.replace("dQw4w9WgXcQ", "yPYZpwSpKmA");
+        let search = format!(r#"[
+            {INVIDIOUS_VIDEO},
+            {{"type": "channel", "author": "Rick Astley"}},
+            {},
+            {{"type": "video", "videoId": "broken"}}
+        ]"#, INVIDIOUS_VIDEO.replace("dQw4w9WgXcQ", "live0000000").replace("213", "0"));
+        let server = MockServer::start(&[
+            ("/api/v1/videos/dQw4w9WgXcQ", 200, INVIDIOUS_VIDEO),
+            ("/api/v1/playlists/PL1?page=1", 200, &page(&format!("{INVIDIOUS_VIDEO}, {second}"))),
+            ("/api/v1/playlists/PL1?page=2", 200, &page(&third)),
+            ("/api/v1/search?q=rick&type=video", 200, &search),
+            ("/api/v1/search?q=rick&type=video&sort=view_count", 200, "[]"),
+        ]).await;
+        let api = instance(ApiKind::Invidious, &server);
+
+        let info = fetch_video(&api, "dQw4w9WgXcQ").await.unwrap();
+        assert_eq!(info.title, "Never Gonna Give You Up");
+        assert_eq!(info.channel, "Rick Astley");
+        assert_eq!(info.channel_url, "https://www.youtube.com/channel/UCuAXFkgsw1L7xaCfnd5JJOw");
+        assert_eq!(info.duration, 213);
+        assert_eq!(info.description, None);
+
+        let list = fetch_playlist(&api, "PL1").await.unwrap();
+        assert_eq!(ids(&list), ["dQw4w9WgXcQ", "AyOqGRjVtls", "yPYZpwSpKmA"]);
+        assert!(list.iter().all(|info| info.playlist.as_deref() == Some("Mix")));
+
+        // Channels, live streams and broken items are left out
+        let list = fetch_search(&api, "rick", &SearchOptions::default()).await.unwrap();
+        assert_eq!(ids(&list), ["dQw4w9WgXcQ"]);
+        let options = SearchOptions { sort: Some(SortOrder::ViewCount), ..Default::default() };
+        assert!(fetch_search(&api, "rick", &options).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn piped() {
+        let video = r#"{"title": "Never Gonna Give You Up", "description": "The video", "uploader": "Rick Astley",
+            "uploaderUrl": "/channel/UC2", "duration": 213}"#;
+        let playlist = format!(
+            r#"{{"name": "Mix", "relatedStreams": [{}, {}], "nextpage": "token"}}"#,
+            piped_stream("aaaaaaaaaaa", 100), piped_stream("bbbbbbbbbbb", 200),
+        );
+        let next = format!(r#"{{"relatedStreams": [{}], "nextpage": null}}"#, piped_stream("ccccccccccc", 300));
+        let search = format!(
+            r#"{{"items": [{}, {}, {}, {{"type": "channel", "name": "Rick Astley"}}]}}"#,
+            piped_stream("aaaaaaaaaaa", 100),
+            piped_stream("shortshort0", 30).replace(r#""isShort": false"#, r#""isShort": true"#),
+            piped_stream("live0000000", -1),
+        );
+        let server = MockServer::start(&[
+            ("/streams/dQw4w9WgXcQ", 200, video),
+            ("/playlists/PL1", 200, &playlist),
+            ("/nextpage/playlists/PL1?nextpage=token", 200, &next),
+            ("/search?q=rick&filter=videos", 200, &search),
+        ]).await;
+        let api = instance(ApiKind::Piped, &server);
+
+        // The ID is not in the response
+        let info = fetch_video(&api, "dQw4w9WgXcQ").await.unwrap();
+        assert_eq!(info.id, "dQw4w9WgXcQ");
+        assert_eq!(info.channel, "Rick Astley");
+        assert_eq!(info.description.as_deref(), Some("The video"));
+        assert_eq!(info.duration, 213);
+
+        let list = fetch_playlist(&api, "PL1").await.unwrap();
+        assert_eq!(ids(&list), ["aaaaaaaaaaa", "bbbbbbbbbbb", "ccccccccccc"]);
+        assert_eq!(list[2].playlist.as_deref(), Some("Mix"));
+
+        // Shorts, live streams and channels are left out
+        let list = fetch_search(&api, "rick", &SearchOptions::default()).await.unwrap();
+        assert_eq!(ids(&list), ["aaaaaaaaaaa"]);
+        // Piped can't sort, yt-dlp does it instead
+        let options = SearchOptions { sort: Some(SortOrder::ViewCount), ..Default::default() };
+        let result = fetch_search(&api, "rick", &options).await;
+        assert!(matches!(result, Err(Error::Unsupported)), "{result:?}");
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        let server = MockServer::start(&[
+            ("/api/v1/videos/dQw4w9WgXcQ", 500, r#"{"error": "Internal error"}"#),
+            ("/api/v1/videos/AyOqGRjVtls", 200, "<html>"),
+            ("/api/v1/videos/yPYZpwSpKmA", 200, r#"{"error": "Video unavailable"}"#),
+            ("/api/v1/playlists/PL1?page=1", 200, r#"{"title": "Empty", "videoCount": 0, "videos": []}"#),
+            ("/api/v1/search?q=rick&type=video", 200, r#"{"error": "Rate limited"}"#),
+        ]).await;
+        let api = instance(ApiKind::Invidious, &server);
+
+        // Every failure of the instance falls back to yt-dlp
+        let result = fetch_video(&api, "dQw4w9WgXcQ").await;
+        assert!(matches!(result, Err(Error::Request(_))), "{result:?}");
+        assert!(from_api(result).is_none());
+        let result = fetch_video(&api, "AyOqGRjVtls").await;
+        assert!(matches!(result, Err(Error::Json(_))), "{result:?}");
+        assert!(from_api(result).is_none());
+        let result = fetch_video(&api, "yPYZpwSpKmA").await;
+        assert!(matches!(result, Err(Error::Malformed)), "{result:?}");
+        assert!(from_api(result).is_none());
+        let result = fetch_playlist(&api, "PL1").await;
+        assert!(matches!(result, Err(Error::Malformed)), "{result:?}");
+        assert!(from_api(result).is_none());
+        let result = fetch_search(&api, "rick", &SearchOptions::default()).await;
+        assert!(matches!(result, Err(Error::Malformed)), "{result:?}");
+        assert!(from_api(result).is_none());
+        // The instance is down
+        let down = YoutubeApi { kind: ApiKind::Piped, url: "http://127.0.0.1:9".to_string() };
+        assert!(from_api(fetch_video(&down, "dQw4w9WgXcQ").await).is_none());
+
+        assert!(from_api::<()>(Err(Error::Disabled)).is_none());
+        // Empty results are answers, not failures
+        assert_eq!(from_api(Ok(Vec::<YoutubeInfo>::new())).map(|list| list.len()), Some(0));
+    }
+}
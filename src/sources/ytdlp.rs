@@ -0,0 +1,209 @@
+use serde_json::Value;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::builder::CreateEmbedAuthor;
+
+use songbird::input::Input;
+
+use tracing::instrument;
+
+use url::Url;
+
+use crate::structs::AudioLink;
+use crate::structs::Metadata;
+use crate::structs::ParseError;
+use crate::structs::ParseResult;
+
+use super::Source;
+use super::Track;
+use super::fallback::Hint;
+use super::runner;
+
+/// Any site supported by yt-dlp, e.g. SoundCloud, Bandcamp or Vimeo
+pub static GENERIC: Ytdlp = Ytdlp {
+    key: "ytdlp",
+    name: "yt-dlp",
+    search_prefix: None,
+};
+
+/// Searching on SoundCloud, the links are played by `GENERIC`
+pub static SOUNDCLOUD: Ytdlp = Ytdlp {
+    key: "sc",
+    name: "SoundCloud",
+    search_prefix: Some("scsearch"),
+};
+
+/// Amount of results fetched by `search()`
+const SEARCH_COUNT: usize = 20;
+
+/// Links handled by the extractors of yt-dlp
+pub struct Ytdlp {
+    key: &'static str,
+    name: &'static str,
+    /// The search key of the yt-dlp extractor, e.g. `scsearch`
+    search_prefix: Option<&'static str>,
+}
+
+#[async_trait]
+impl Source for Ytdlp {
+    fn key(&self) -> &'static str {
+        self.key
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        // Only the generic instance resolves links, it would accept the same ones anyway
+        self.search_prefix.is_none() && matches!(url.scheme(), "http" | "https")
+    }
+
+    async fn resolve(&self, url: &Url) -> Result<Option<ParseResult>, ParseError> {
+        match get_info(url.as_str()).await {
+            Ok(InfoType::Single(info)) => Ok(Some(ParseResult::Single(AudioLink::new(info)))),
+            Ok(InfoType::Playlist(list, title)) => Ok(Some(ParseResult::Multiple(
+                list.into_iter().map(AudioLink::new).collect(),
+                Metadata { title },
+            ))),
+            Err(Error::Ytdlp(runner::Error::Unsupported)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<AudioLink>, String> {
+        let Some(prefix) = self.search_prefix else {
+            return Err(format!("Searching on {} is not supported", self.name));
+        };
+        match get_info(&format!("{prefix}{SEARCH_COUNT}:{query}")).await {
+            Ok(InfoType::Single(info)) => Ok(vec![AudioLink::new(info)]),
+            Ok(InfoType::Playlist(list, _)) => Ok(list.into_iter().map(AudioLink::new).collect()),
+            Err(err) => Err(format!("Search failed: {}", err)),
+        }
+    }
+
+    async fn load(&self, id: &str) -> anyhow::Result<AudioLink> {
+        Ok(AudioLink::new(load(id).await?))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct YtdlpInfo {
+    /// Name of the yt-dlp extractor, e.g. `Soundcloud`
+    pub extractor: String,
+    pub title: String,
+    pub uploader: Option<String>,
+    /// Duration in seconds, 0 if unknown
+    pub duration: u32,
+    pub webpage_url: String,
+}
+
+impl YtdlpInfo {
+    /// Read the fields from the JSON output of yt-dlp, flat playlist entries only have some of them
+    fn from_value(value: &Value) -> Option<Self> {
+        let str_field = |keys: &[&str]| keys.iter().find_map(|key| value[key].as_str()).map(str::to_string);
+        let webpage_url = str_field(&["webpage_url", "url"])?;
+        Some(YtdlpInfo {
+            extractor: str_field(&["extractor_key", "ie_key"]).unwrap_or_else(|| "Generic".to_string()),
+            title: str_field(&["title", "fulltitle"]).unwrap_or_else(|| webpage_url.clone()),
+            uploader: str_field(&["uploader", "channel", "artist"]),
+            duration: value["duration"].as_f64().unwrap_or_default() as u32,
+            webpage_url,
+        })
+    }
+}
+
+#[async_trait]
+impl Track for YtdlpInfo {
+    /// The links found by searching on other sources are played the same way
+    fn source(&self) -> &'static dyn Source {
+        &GENERIC
+    }
+
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    fn hint(&self) -> Option<Hint> {
+        Some(Hint {
+            title: self.title.clone(),
+            channel: self.uploader.clone(),
+            duration: self.duration,
+            link: Some(self.webpage_url.clone()),
+            video_id: None,
+        })
+    }
+
+    fn input(&self) -> Input {
+        runner::YtdlpStream::new(self.webpage_url.clone()).into()
+    }
+
+    fn unload(&self) -> String {
+        self.webpage_url.clone()
+    }
+
+    async fn embed(&self, _position: Option<u32>) -> CreateEmbed {
+        let mut m = CreateEmbed::new()
+            .title(&self.title)
+            .url(&self.webpage_url)
+            .field("Duration", self.time_str(), true)
+            .author(CreateEmbedAuthor::new(format!("Audio source from {}", self.extractor)));
+        if let Some(uploader) = &self.uploader {
+            m = m.field("Uploader", uploader, true);
+        }
+        m
+    }
+}
+
+pub enum InfoType {
+    Single(YtdlpInfo),
+    /// The entries and the title of the playlist
+    Playlist(Vec<YtdlpInfo>, String),
+}
+
+#[instrument]
+pub async fn get_info(url: &str) -> Result<InfoType, Error> {
+    let values = run(&["--flat-playlist"], url).await?;
+    let title = values.first()
+        .and_then(|v| v["playlist_title"].as_str().or(v["playlist"].as_str()))
+        .unwrap_or("Unknown")
+        .to_string();
+    let mut list = values.iter()
+        .filter_map(YtdlpInfo::from_value)  // ignore the failed items
+        .collect::<Vec<_>>();
+
+    match list.len() {
+        0 => Err(Error::UnknownParse),
+        1 => Ok(InfoType::Single(list.remove(0))),
+        _ => Ok(InfoType::Playlist(list, title)),
+    }
+}
+
+#[instrument]
+pub async fn load(url: &str) -> Result<YtdlpInfo, Error> {
+    run(&["--no-playlist"], url).await?
+        .first()
+        .and_then(YtdlpInfo::from_value)
+        .ok_or(Error::UnknownParse)
+}
+
+/// Run yt-dlp and parse the JSON lines it prints
+async fn run(args: &[&str], url: &str) -> Result<Vec<Value>, Error> {
+    let mut all_args = vec!["-j", "--skip-download", "--no-warning"];
+    all_args.extend_from_slice(args);
+    let result = runner::run(&all_args, url).await?;
+    Ok(result.lines().flat_map(serde_json::from_str::<Value>).collect())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Ytdlp(#[from] runner::Error),
+    #[error("Unknown parser error")]
+    UnknownParse,
+}
@@ -1,25 +1,39 @@
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::ops::Deref;
+use std::sync::Arc;
 
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
+use serde::de::Error as _;
+use serde::ser::SerializeMap;
 use songbird::input::Input;
-use songbird::input::YoutubeDl;
 
-use crate::CLIENT;
-use crate::sources::youtube;
-use crate::sources::youtube::YoutubeInfo;
-use crate::sources::youtube::get_yt_info;
+use tracing::warn;
 
+use crate::sources::SOURCES;
+use crate::sources::Track;
+use crate::sources::canonicalize;
+use crate::sources::find_source;
+use crate::sources::split_query;
+
+use super::ParseError;
+
+/// A song from any source, cheap to clone
+///
+/// The metadata is the `Track` of the source, use `downcast()` for the details specific to a source
 #[derive(Debug, Clone)]
-pub enum AudioLink {
-    Youtube(YoutubeInfo),
-}
+pub struct AudioLink(Arc<dyn Track>);
 
 /// Lazy version of `AudioLink`, use `load()` to get `AudioLink`
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum UnloadedAudioLink {
-    #[serde(rename = "yt")]
-    Youtube(String),
+///
+/// Serialized as `{ <source key>: <id> }`
+#[derive(Debug, Clone)]
+pub struct UnloadedAudioLink {
+    pub source: String,
+    pub id: String,
 }
 
 pub enum ParseResult {
@@ -32,77 +46,108 @@ pub struct Metadata {
 }
 
 impl AudioLink {
-    pub async fn parse(link: impl Into<String>) -> Result<ParseResult, String> {
+    /// Load the link with the first source accepting it, failures are logged with their cause
+    pub async fn parse(link: impl Into<String>) -> Result<ParseResult, ParseError> {
         let link = link.into();
-        let url = url::Url::parse(&link).map_err(|err| format!("URL parse error: {}", err))?;
-        match url.host_str() {
-            Some("www.youtube.com")
-            | Some("youtube.com")
-            | Some("m.youtube.com")
-            | Some("music.youtube.com")
-            | Some("youtu.be") => match get_yt_info(&link).await {
-                Ok(youtube::InfoType::Video(info)) => {
-                    Ok(ParseResult::Single(AudioLink::Youtube(info)))
-                }
-                Ok(youtube::InfoType::Playlist(infos)) => {
-                    let title = infos[0]
-                        .playlist
-                        .clone()
-                        .unwrap_or_else(|| String::from("Unknown"));
-                    let list = infos
-                        .into_iter()
-                        .map(|entry| AudioLink::Youtube(entry))
-                        .collect();
-                    Ok(ParseResult::Multiple(list, Metadata { title }))
-                }
-                _ => Err("Data fetch failed".to_string()),
-            },
-            _ => Err("Unsupported URL".to_string()),
+        let result = Self::resolve(&link).await;
+        if let Err(err) = &result {
+            warn!("Failed to load `{link}`: {err}");
         }
+        result
     }
-}
 
-impl From<AudioLink> for Input {
-    fn from(audio: AudioLink) -> Self {
-        match audio {
-            AudioLink::Youtube(info) => YoutubeDl::new(CLIENT.clone(), format!("https://www.youtube.com/watch?v={}", info.id)).into(),
+    async fn resolve(link: &str) -> Result<ParseResult, ParseError> {
+        let url = canonicalize(url::Url::parse(link)?);
+        for source in SOURCES.iter().filter(|source| source.matches(&url)) {
+            if let Some(result) = source.resolve(&url).await? {
+                return Ok(result);
+            }
         }
+        Err(ParseError::UnsupportedHost(url.host_str().unwrap_or(link).to_string()))
     }
-}
 
-impl Display for AudioLink {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            AudioLink::Youtube(info) => {
-                write!(f, "{}", info.title)
-            },
-        }
+    /// Check if the input should be handled by `parse()` instead of `search()`
+    pub fn is_link(input: &str) -> bool {
+        url::Url::parse(input.trim())
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
     }
-}
 
-impl AudioLink {
-    pub fn time(&self) -> u32 {
-        match self {
-            Self::Youtube(info) => info.duration,
+    /// Search for `query` and return the top result,
+    /// the query can be prefixed with the source to search on, e.g. `yt:`
+    pub async fn search(query: &str) -> Result<AudioLink, ParseError> {
+        let (source, query) = split_query(query);
+        if query.is_empty() {
+            return Err(ParseError::NotFound("Empty search query".to_string()));
         }
+        source.search(query).await
+            .inspect_err(|err| warn!("Search for `{query}` failed: {err}"))
+            .map_err(ParseError::Failed)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ParseError::NotFound(format!("No result found for `{}`", query)))
     }
 
-    pub fn time_str(&self) -> String {
-        let t = self.time();
-        format!("{}:{:02}", t / 60, t % 60)
+    pub fn new(track: impl Track) -> Self {
+        Self(Arc::new(track))
+    }
+
+    /// The metadata of the song if it comes from the source using `T`
+    pub fn downcast<T: Track>(&self) -> Option<&T> {
+        let track: &dyn std::any::Any = &*self.0;
+        track.downcast_ref()
     }
 
     pub fn unload(&self) -> UnloadedAudioLink {
-        match self {
-            Self::Youtube(info) => UnloadedAudioLink::Youtube(info.id.to_owned()),
+        UnloadedAudioLink {
+            source: self.source().key().to_string(),
+            id: self.0.unload(),
         }
     }
 }
 
+impl Deref for AudioLink {
+    type Target = dyn Track;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl From<AudioLink> for Input {
+    fn from(audio: AudioLink) -> Self {
+        audio.input()
+    }
+}
+
+impl Display for AudioLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.title())
+    }
+}
+
 impl UnloadedAudioLink {
     pub async fn load(self) -> anyhow::Result<AudioLink> {
-        match self {
-            Self::Youtube(id) => Ok(AudioLink::Youtube(youtube::load(&id).await?)),
+        let source = find_source(&self.source)
+            .ok_or_else(|| anyhow::anyhow!("Unknown source `{}`", self.source))?;
+        source.load(&self.id).await
+    }
+}
+
+impl Serialize for UnloadedAudioLink {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&self.source, &self.id)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for UnloadedAudioLink {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = HashMap::<String, String>::deserialize(deserializer)?;
+        if map.len() != 1 {
+            return Err(D::Error::invalid_length(map.len(), &"a map with a single entry"));
         }
+        let (source, id) = map.into_iter().next().expect("map has one entry");
+        Ok(UnloadedAudioLink { source, id })
     }
 }
@@ -1,13 +1,25 @@
 use std::collections::VecDeque;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use dashmap::DashMap;
 
+use songbird::Call;
+use songbird::tracks::TrackHandle;
+
+use tokio::task::AbortHandle;
+
+use serenity::all::ChannelId;
 use serenity::all::GuildId;
 use serenity::all::UserId;
 
+use crate::sources::audio_cache::AUDIO_CACHE;
+use crate::sources::youtube::PlaylistInfo;
+
 use super::AudioLink;
+use super::SuggestionCache;
 
 #[derive(Debug)]
 pub struct PerGuildData {
@@ -18,8 +30,28 @@ pub struct PerGuildData {
 pub struct PlayerData {
    pub queue: VecDeque<AudioLink>,
    pub state: PlayerState,
+   /// The handle of the track on play, used to read the position
+   pub track: Option<TrackHandle>,
+   /// Whether the song on play is read from the audio cache
+   pub from_cache: bool,
+   /// Songs which failed to play in a row
+   pub failures: u32,
+   /// Times the song on play was resumed after its stream dropped
+   pub resumes: u32,
+   /// Times the player was stopped or left the channel, the playlists still loading stop when it changes
+   pub stops: u64,
    pub loop_policy: LoopPolicy,
-   pub search_item: HashMap<UserId, Vec<AudioLink>>
+   pub duplicate_policy: DuplicatePolicy,
+   pub search_item: HashMap<UserId, Vec<SearchItem>>,
+   /// The channel where the songs on air of radio streams are announced, and the announcing task
+   pub announcer: Option<(ChannelId, AbortHandle)>,
+}
+
+/// An option listed by `search`, picked with `select`
+#[derive(Debug, Clone)]
+pub enum SearchItem {
+    Audio(AudioLink),
+    Playlist(PlaylistInfo),
 }
 
 #[derive(Debug)]
@@ -36,8 +68,19 @@ pub enum LoopPolicy {
     Normal,
     /// Add the song back to the queue
     Loop,
-    /// Put the song in the shuffle pool
-    Random,
+}
+
+/// Determine what to do with the songs already in the queue or on play, when they are added again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum DuplicatePolicy {
+    #[name = "allow"]
+    Allow,
+    /// Add them, and tell how many were duplicates
+    #[name = "warn"]
+    Warn,
+    /// Skip them
+    #[name = "reject"]
+    Reject,
 }
 
 impl PerGuildData {
@@ -46,22 +89,85 @@ impl PerGuildData {
             player: PlayerData {
                 queue: VecDeque::new(),
                 state: PlayerState::Offline,
+                track: None,
+                from_cache: false,
+                failures: 0,
+                resumes: 0,
+                stops: 0,
                 loop_policy: LoopPolicy::Normal,
+                duplicate_policy: DuplicatePolicy::Warn,
                 search_item: HashMap::new(),
+                announcer: None,
             }
         }
     }
 }
 
+impl PlayerData {
+    /// Play `audio` on the call and mark it as the song on play
+    pub fn play(&mut self, call: &mut Call, audio: AudioLink) {
+        self.from_cache = audio.video().is_some_and(|video| AUDIO_CACHE.contains(&video.id));
+        self.track = Some(call.play(audio.clone().into()));
+        self.state = PlayerState::Playing(audio);
+        self.resumes = 0;
+    }
+
+    /// Add the songs to the end of the queue following the duplicate policy,
+    /// returns the amount of songs which were already in the queue or on play
+    pub fn enqueue(&mut self, songs: impl IntoIterator<Item = AudioLink>) -> usize {
+        if self.duplicate_policy == DuplicatePolicy::Allow {
+            self.queue.extend(songs);
+            return 0;
+        }
+        let playing = match &self.state {
+            PlayerState::Playing(audio) => Some(audio),
+            _ => None,
+        };
+        let mut seen = self.queue.iter()
+            .chain(playing)
+            .map(|audio| audio.identity())
+            .collect::<HashSet<_>>();
+        let mut duplicates = 0;
+        for audio in songs {
+            if !seen.insert(audio.identity()) {
+                duplicates += 1;
+                if self.duplicate_policy == DuplicatePolicy::Reject {
+                    continue;
+                }
+            }
+            self.queue.push_back(audio);
+        }
+        duplicates
+    }
+
+    /// Play the song on play again from `position`, the input is created again so the stream is resolved anew,
+    /// live streams are reconnected where they are on air
+    pub fn resume(&mut self, call: &mut Call, position: Duration) {
+        let PlayerState::Playing(audio) = &self.state else { return };
+        let track = call.play(audio.clone().into());
+        if !audio.is_live() {
+            let _ = track.seek(position);
+        }
+        self.track = Some(track);
+        self.resumes += 1;
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct Data(Arc<DashMap<GuildId, PerGuildData>>);
+pub struct Data {
+    guilds: Arc<DashMap<GuildId, PerGuildData>>,
+    pub suggestion: Arc<SuggestionCache>,
+}
 
 impl Data {
     pub fn new() -> Self {
-        Data(Arc::new(DashMap::new()))
+        Data {
+            guilds: Arc::new(DashMap::new()),
+            suggestion: Arc::new(SuggestionCache::new()),
+        }
     }
     
     pub fn get(&self, guild_id: GuildId) -> dashmap::mapref::one::RefMut<'_, GuildId, PerGuildData> {
-        self.0.entry(guild_id).or_insert_with(PerGuildData::new)
+        self.guilds.entry(guild_id).or_insert_with(PerGuildData::new)
     }
 }
@@ -1,5 +1,9 @@
 mod audio_link;
 mod context_data;
+mod parse_error;
+mod suggestion;
 
 pub use audio_link::*;
 pub use context_data::*;
+pub use parse_error::*;
+pub use suggestion::*;
@@ -0,0 +1,157 @@
+use crate::sources::catalog;
+use crate::sources::runner;
+use crate::sources::youtube;
+use crate::sources::ytdlp;
+
+/// Why a link couldn't be loaded
+///
+/// The text of each variant is the full cause, for the logs,
+/// the users are shown `message()` instead
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ParseError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Unsupported URL: {0}")]
+    UnsupportedHost(String),
+    /// The site is supported but not this kind of its links, the text is shown to the users
+    #[error("Unsupported link: {0}")]
+    UnsupportedLink(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Private: {0}")]
+    Private(String),
+    #[error("Age-restricted: {0}")]
+    AgeRestricted(String),
+    #[error("Geo-blocked: {0}")]
+    GeoBlocked(String),
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    #[error("Not audio: {0}")]
+    NotAudio(String),
+    #[error("Too large: {0}")]
+    TooLarge(String),
+    /// Other failures, only the logs tell why
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl ParseError {
+    /// A friendly message in the language of `locale`, English by default
+    pub fn message(&self, locale: Option<&str>) -> String {
+        let zh = locale == Some("zh-TW");
+        match self {
+            ParseError::InvalidUrl(_) if zh => "這不是有效的連結".to_string(),
+            ParseError::InvalidUrl(_) => "That is not a valid link".to_string(),
+            ParseError::UnsupportedHost(_) if zh => "不支援這個網站的連結".to_string(),
+            ParseError::UnsupportedHost(_) => "Links of this site are not supported".to_string(),
+            ParseError::UnsupportedLink(reason) if zh => format!("不支援這種連結：{reason}"),
+            ParseError::UnsupportedLink(reason) => reason.clone(),
+            ParseError::NotFound(_) if zh => "找不到連結的內容，可能已被移除".to_string(),
+            ParseError::NotFound(_) => "Nothing was found at this link, it may have been removed".to_string(),
+            ParseError::Private(_) if zh => "這是私人內容，無法播放".to_string(),
+            ParseError::Private(_) => "This content is private and can't be played".to_string(),
+            ParseError::AgeRestricted(_) if zh => "這個內容有年齡限制，無法播放".to_string(),
+            ParseError::AgeRestricted(_) => "This content is age-restricted and can't be played".to_string(),
+            ParseError::GeoBlocked(_) if zh => "這個內容在機器人所在的國家無法使用".to_string(),
+            ParseError::GeoBlocked(_) => "This content is not available in the bot's country".to_string(),
+            ParseError::RateLimited(_) if zh => "網站限制了請求次數，請稍後再試".to_string(),
+            ParseError::RateLimited(_) => "The site is limiting the requests, please try again later".to_string(),
+            ParseError::Timeout(_) if zh => "網站回應逾時，請再試一次".to_string(),
+            ParseError::Timeout(_) => "The site took too long to respond, please try again".to_string(),
+            ParseError::NotAudio(_) if zh => "這不是音訊檔案，或連結已過期".to_string(),
+            ParseError::NotAudio(_) => "This is not an audio file, or the link has expired".to_string(),
+            ParseError::TooLarge(_) if zh => "檔案太大".to_string(),
+            ParseError::TooLarge(_) => "The file is too large".to_string(),
+            ParseError::Failed(_) if zh => "無法載入這個連結".to_string(),
+            ParseError::Failed(_) => "The link could not be loaded".to_string(),
+        }
+    }
+}
+
+impl From<url::ParseError> for ParseError {
+    fn from(value: url::ParseError) -> Self {
+        ParseError::InvalidUrl(value.to_string())
+    }
+}
+
+/// The errors of `UnloadedAudioLink::load()`, keeps the kind of those raised by the sources
+impl From<anyhow::Error> for ParseError {
+    fn from(value: anyhow::Error) -> Self {
+        let value = match value.downcast::<ParseError>() {
+            Ok(err) => return err,
+            Err(value) => value,
+        };
+        match value.downcast::<youtube::Error>() {
+            Ok(err) => err.into(),
+            Err(value) => ParseError::Failed(value.to_string()),
+        }
+    }
+}
+
+impl From<runner::Error> for ParseError {
+    fn from(value: runner::Error) -> Self {
+        let cause = value.to_string();
+        match value {
+            runner::Error::Timeout => ParseError::Timeout(cause),
+            runner::Error::Unsupported => ParseError::UnsupportedHost(cause),
+            runner::Error::Unavailable(_) => ParseError::NotFound(cause),
+            runner::Error::Private(_) => ParseError::Private(cause),
+            runner::Error::AgeRestricted => ParseError::AgeRestricted(cause),
+            runner::Error::GeoBlocked => ParseError::GeoBlocked(cause),
+            runner::Error::RateLimited => ParseError::RateLimited(cause),
+            _ => ParseError::Failed(cause),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ParseError {
+    fn from(value: reqwest::Error) -> Self {
+        // The text of reqwest errors leaves out the underlying one
+        let cause = match std::error::Error::source(&value) {
+            Some(source) => format!("{value}: {source}"),
+            None => value.to_string(),
+        };
+        if value.is_timeout() {
+            return ParseError::Timeout(cause);
+        }
+        match value.status().map(|status| status.as_u16()) {
+            Some(404 | 410) => ParseError::NotFound(cause),
+            Some(401 | 403) => ParseError::Private(cause),
+            Some(429) => ParseError::RateLimited(cause),
+            _ => ParseError::Failed(format!("Request failed: {cause}")),
+        }
+    }
+}
+
+impl From<youtube::Error> for ParseError {
+    fn from(value: youtube::Error) -> Self {
+        match value {
+            youtube::Error::Ytdlp(err) => err.into(),
+            youtube::Error::InvalidId(id) => ParseError::InvalidUrl(id),
+            err => ParseError::Failed(format!("Data fetch failed: {err}")),
+        }
+    }
+}
+
+impl From<ytdlp::Error> for ParseError {
+    fn from(value: ytdlp::Error) -> Self {
+        match value {
+            ytdlp::Error::Ytdlp(err) => err.into(),
+            err => ParseError::Failed(format!("Data fetch failed: {err}")),
+        }
+    }
+}
+
+impl From<catalog::Error> for ParseError {
+    fn from(value: catalog::Error) -> Self {
+        match value {
+            catalog::Error::UnsupportedLink => ParseError::UnsupportedHost(value.to_string()),
+            catalog::Error::ApplePlaylist => ParseError::UnsupportedLink(value.to_string()),
+            catalog::Error::NotFound => ParseError::NotFound(value.to_string()),
+            catalog::Error::Request(err) => err.into(),
+            err => ParseError::Failed(err.to_string()),
+        }
+    }
+}
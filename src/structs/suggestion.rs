@@ -0,0 +1,204 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use dashmap::DashMap;
+
+use serenity::all::UserId;
+
+use crate::sources::youtube::YoutubeInfo;
+
+/// How long a cached search result stays valid
+const TTL: Duration = Duration::from_secs(600);
+/// Maximum amount of cached queries
+const CAPACITY: usize = 256;
+
+/// Search results for autocomplete, cached by the (normalized) query typed so far
+///
+/// A longer query is served from the results of its longest cached prefix while some of them still match
+#[derive(Debug, Default)]
+pub struct SuggestionCache {
+    entries: DashMap<String, (Instant, Vec<YoutubeInfo>)>,
+    /// The latest autocomplete request of each user still waiting, used for debouncing
+    pending: DashMap<UserId, u64>,
+    counter: AtomicU64,
+}
+
+impl SuggestionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, query: &str) -> Option<Vec<YoutubeInfo>> {
+        let key = normalize(query);
+        if let Some(entry) = self.entries.get(&key) {
+            let (time, ref list) = *entry;
+            if time.elapsed() < TTL {
+                return Some(list.clone());
+            }
+            drop(entry);
+            self.entries.remove(&key);
+        }
+        self.get_by_prefix(&key)
+    }
+
+    /// The results of the longest fresh prefix of `key` whose title or channel contain every word of it
+    fn get_by_prefix(&self, key: &str) -> Option<Vec<YoutubeInfo>> {
+        let entry = self.entries.iter()
+            .filter(|entry| key.starts_with(entry.key().as_str()) && entry.value().0.elapsed() < TTL)
+            .max_by_key(|entry| entry.key().len())?;
+        let words = key.split(' ').collect::<Vec<_>>();
+        let list = entry.value().1.iter()
+            .filter(|info| {
+                let text = format!("{} {}", info.title, info.channel).to_lowercase();
+                words.iter().all(|word| text.contains(word))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        (!list.is_empty()).then_some(list)
+    }
+
+    pub fn insert(&self, query: &str, list: Vec<YoutubeInfo>) {
+        if self.entries.len() >= CAPACITY {
+            self.entries.retain(|_, (time, _)| time.elapsed() < TTL);
+        }
+        if self.entries.len() >= CAPACITY {
+            let oldest = self.entries.iter()
+                .min_by_key(|entry| entry.value().0)
+                .map(|entry| entry.key().clone());
+            if let Some(key) = oldest {
+                self.entries.remove(&key);
+            }
+        }
+        self.entries.insert(normalize(query), (Instant::now(), list));
+    }
+
+    /// Register a new request from `user`, returns a ticket for `is_latest()`
+    pub fn begin(&self, user: UserId) -> u64 {
+        let ticket = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.pending.insert(user, ticket);
+        ticket
+    }
+
+    /// Check if no newer request from `user` arrived since `ticket` was issued,
+    /// the latest request is then no longer pending
+    pub fn is_latest(&self, user: UserId, ticket: u64) -> bool {
+        self.pending.remove_if(&user, |_, t| *t == ticket).is_some()
+    }
+}
+
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(title: &str, channel: &str) -> YoutubeInfo {
+        YoutubeInfo {
+            id: title.to_string(),
+            title: title.to_string(),
+            description: None,
+            channel: channel.to_string(),
+            channel_url: String::new(),
+            duration: 60,
+            playlist: None,
+        }
+    }
+
+    fn titles(list: Option<Vec<YoutubeInfo>>) -> Option<Vec<String>> {
+        list.map(|list| list.into_iter().map(|info| info.title).collect())
+    }
+
+    fn expired() -> Instant {
+        Instant::now() - TTL - Duration::from_secs(1)
+    }
+
+    #[test]
+    fn exact_query() {
+        let cache = SuggestionCache::new();
+        cache.insert("  Lofi   Beats ", vec![video("Lofi beats to study", "Lofi Girl")]);
+        assert_eq!(titles(cache.get("lofi beats")), Some(vec!["Lofi beats to study".to_string()]));
+        assert_eq!(titles(cache.get("LOFI BEATS  ")), Some(vec!["Lofi beats to study".to_string()]));
+        assert_eq!(titles(cache.get("jazz")), None);
+    }
+
+    #[test]
+    fn prefix() {
+        let cache = SuggestionCache::new();
+        cache.insert("lo", vec![video("Lofi hip hop", "Lofi Girl"), video("Love story", "Taylor Swift")]);
+        cache.insert("lofi", vec![
+            video("Lofi hip hop radio", "Lofi Girl"),
+            video("Jazz lofi", "Chill Cafe"),
+            video("Rain sounds", "Lofi Nature"),
+        ]);
+        let cases = [
+            // The longest cached prefix is used, every word has to be in the title or the channel
+            ("lofi hip", Some(vec!["Lofi hip hop radio"])),
+            ("lofi jazz", Some(vec!["Jazz lofi"])),
+            ("lofi nature", Some(vec!["Rain sounds"])),
+            ("lov", Some(vec!["Love story"])),
+            ("lofi metal", None),
+            ("jazz", None),
+        ];
+        for (query, expected) in cases {
+            let expected = expected.map(|list| list.into_iter().map(str::to_string).collect::<Vec<_>>());
+            assert_eq!(titles(cache.get(query)), expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn expiry() {
+        let cache = SuggestionCache::new();
+        cache.insert("lofi", vec![video("Lofi hip hop", "Lofi Girl")]);
+        cache.entries.insert("lo".to_string(), (expired(), vec![video("Love story", "Taylor Swift")]));
+        cache.entries.insert("rain".to_string(), (expired(), vec![video("Rain sounds", "Nature")]));
+        assert_eq!(titles(cache.get("rain")), None);
+        assert!(!cache.entries.contains_key("rain"), "expired entries are removed when read");
+        // Expired prefixes are not used either
+        assert_eq!(titles(cache.get("lov")), None);
+        assert_eq!(titles(cache.get("lofi hip")), Some(vec!["Lofi hip hop".to_string()]));
+    }
+
+    #[test]
+    fn capacity() {
+        let cache = SuggestionCache::new();
+        let now = Instant::now();
+        for i in 0..CAPACITY {
+            let time = now - Duration::from_secs((CAPACITY - i) as u64);
+            cache.entries.insert(format!("query {i}"), (time, vec![]));
+        }
+        // The oldest entry makes room
+        cache.insert("new", vec![]);
+        assert_eq!(cache.entries.len(), CAPACITY);
+        assert!(!cache.entries.contains_key("query 0"));
+        assert!(cache.entries.contains_key("query 1"));
+        assert!(cache.entries.contains_key("new"));
+
+        // The expired entries are dropped before the fresh ones
+        cache.entries.insert("query 1".to_string(), (expired(), vec![]));
+        cache.entries.insert("query 2".to_string(), (expired(), vec![]));
+        cache.insert("newer", vec![]);
+        assert_eq!(cache.entries.len(), CAPACITY - 1);
+        assert!(!cache.entries.contains_key("query 1"));
+        assert!(!cache.entries.contains_key("query 2"));
+        assert!(cache.entries.contains_key("query 3"));
+    }
+
+    #[test]
+    fn debounce() {
+        let cache = SuggestionCache::new();
+        let (alice, bob) = (UserId::new(1), UserId::new(2));
+        let first = cache.begin(alice);
+        let second = cache.begin(alice);
+        let other = cache.begin(bob);
+        assert!(!cache.is_latest(alice, first), "a newer request replaced it");
+        assert!(cache.is_latest(alice, second));
+        // Answered, so it's no longer pending
+        assert!(!cache.is_latest(alice, second));
+        assert!(cache.pending.get(&alice).is_none());
+        assert!(cache.is_latest(bob, other));
+    }
+}